@@ -86,6 +86,39 @@ export class TelegramService {
     }
   }
 
+  // Telegram accepts at most 100 message ids per deleteMessages call
+  async deleteMessages(messageIds: number[]): Promise<void> {
+    const token = this.configService.get<string>('TELEGRAM_BOT_TOKEN');
+    const chatId = this.configService.get<string>('TELEGRAM_CHAT_ID');
+
+    if (!token || !chatId) {
+      throw new HttpException(
+        'Telegram token or chat ID not configured',
+        HttpStatus.INTERNAL_SERVER_ERROR,
+      );
+    }
+
+    const url = `https://api.telegram.org/bot${token}/deleteMessages`;
+
+    try {
+      for (let i = 0; i < messageIds.length; i += 100) {
+        await axios.post(url, {
+          chat_id: chatId,
+          message_ids: messageIds.slice(i, i + 100),
+        });
+      }
+    } catch (error) {
+      this.logger.error(
+        'Telegram message delete error:',
+        error.response?.data || error.message,
+      );
+      throw new HttpException(
+        'Failed to delete messages from Telegram',
+        HttpStatus.BAD_GATEWAY,
+      );
+    }
+  }
+
   async getFile(fileId: string): Promise<Buffer> {
     const token = this.configService.get<string>('TELEGRAM_BOT_TOKEN');
 
@@ -33,7 +33,16 @@ export class CreateTrashDto {
   expireAt?: Date;
 }
 
-export class CreateTextTrashDto {
+// What the server checks before handing out a trash, sent next to the encryption
+// metadata so that it is never stored in it or served back
+export class TrashAccessDto {
+  passcode_hash?: string;
+  slot_passcode_hashes?: Record<string, string>;
+  // Only its hash is stored; PATCH and DELETE ask for it
+  owner_token?: string;
+}
+
+export class CreateTextTrashDto extends TrashAccessDto {
   enc_trash_text: string;
   text_length: number;
   expire_at?: Date;
@@ -41,15 +50,16 @@ export class CreateTextTrashDto {
     encryption_type: string;
     expire_at: string | null;
     original_length: number;
-    passcode_hash: string;
+    passcode_hash?: string;
+    [key: string]: unknown;
   };
 }
 
-export class CreateFileTrashDto {
+export class CreateFileTrashDto extends TrashAccessDto {
   message_ids: number[];
   file_ids: string[];
   encryption_metadata: {
-    passcode_hash: string;
+    passcode_hash?: string;
     expire_at?: Date;
     original_size: number;
     total_chunks: number;
@@ -57,5 +67,6 @@ export class CreateFileTrashDto {
     encryption_type: string;
     filename: string;
     mime_type?: string;
+    [key: string]: unknown;
   };
 }
@@ -2,3 +2,16 @@ import { PartialType } from '@nestjs/mapped-types';
 import { CreateTrashDto } from './create-trash.dto';
 
 export class UpdateTrashDto extends PartialType(CreateTrashDto) {}
+
+export class UpdateEncryptionMetadataDto {
+  // Proves the caller may rewrite the metadata
+  owner_token?: string;
+  // Only checked for trashes created before owner tokens
+  passcode?: string;
+  // Only the key slots are applied; the rest of the stored metadata is kept
+  encryption_metadata: {
+    key_slots: unknown[];
+  };
+  // Passcode hashes of new or changed slots, by slot id
+  slot_passcode_hashes?: Record<string, string>;
+}
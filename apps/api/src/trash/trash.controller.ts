@@ -2,6 +2,8 @@ import {
   Controller,
   Get,
   Post,
+  Patch,
+  Delete,
   Body,
   Param,
   Query,
@@ -15,6 +17,7 @@ import { FileInterceptor } from '@nestjs/platform-express';
 import type { Response } from 'express';
 import { TrashService } from './trash.service';
 import { CreateFileTrashDto, CreateTextTrashDto } from './dto/create-trash.dto';
+import { UpdateEncryptionMetadataDto } from './dto/update-trash.dto';
 import { type Base } from 'src/lib/utils';
 import { TelegramService } from 'src/telegram/telegram.service';
 
@@ -56,10 +59,8 @@ export class TrashController {
         throw new HttpException('Trash not found', 404);
       }
 
-      if (trashContent.trash.encrypted) {
-        if (trashContent.trash.passcodeHash != passcode) {
-          throw new HttpException('Passcode is incorrect', 403);
-        }
+      if (!this.trashService.isPasscodeValid(trashContent.trash, passcode)) {
+        throw new HttpException('Passcode is incorrect', 403);
       }
 
       return {
@@ -69,6 +70,9 @@ export class TrashController {
           enc_trash_text: trashContent.enc_trash_text,
           encryption_type: trashContent.encryption_type,
           text_length: trashContent.text_length,
+          encryption_metadata: this.trashService.publicMetadata(
+            trashContent.trash.encryptionMetadata,
+          ),
         },
       };
     } catch (error) {
@@ -118,6 +122,9 @@ export class TrashController {
           file_name: trashContent.original_name,
           mime_type: trashContent.mime_type,
           file_size: trashContent.file_size,
+          encryption_metadata: this.trashService.publicMetadata(
+            trashContent.trash.encryptionMetadata,
+          ),
         },
       };
     } catch (error) {
@@ -234,6 +241,44 @@ export class TrashController {
     }
   }
 
+  @Patch(':id')
+  async updateEncryptionMetadata(
+    @Param('id') id: string,
+    @Body() updateDto: UpdateEncryptionMetadataDto,
+  ): Promise<Base> {
+    try {
+      await this.trashService.updateEncryptionMetadata(id, updateDto);
+      return {
+        success: true,
+        message: 'Trash metadata updated successfully',
+      };
+    } catch (error) {
+      this.logger.error('Error updating trash metadata:', error);
+      if (error instanceof HttpException) throw error;
+      throw new HttpException(error.message, 500);
+    }
+  }
+
+  // Removes the trash and, best effort, the Telegram messages holding its chunks
+  @Delete(':id')
+  async deleteTrash(
+    @Param('id') id: string,
+    @Query('owner_token') ownerToken: string | undefined,
+    @Query('passcode') passcode: string | undefined,
+  ): Promise<Base> {
+    try {
+      await this.trashService.deleteTrash(id, ownerToken, passcode);
+      return {
+        success: true,
+        message: 'Trash deleted successfully',
+      };
+    } catch (error) {
+      this.logger.error('Error deleting trash:', error);
+      if (error instanceof HttpException) throw error;
+      throw new HttpException(error.message, 500);
+    }
+  }
+
   // KEEP THIS METHOD LAST:: IT MESSES UP WHOLE LOGIC IT TOOK ME ALOT TO UNDERstand THIS!!!!!
 
   @Get(':id')
@@ -1,19 +1,29 @@
 import { HttpException, Injectable, Logger } from '@nestjs/common';
+import { createHash, timingSafeEqual } from 'crypto';
 import { CreateFileTrashDto, CreateTextTrashDto } from './dto/create-trash.dto';
+import { UpdateEncryptionMetadataDto } from './dto/update-trash.dto';
 import { generateId, id } from 'src/lib/utils';
 import { ErrorResponse } from 'src/lib/exception-filter';
 import { PrismaService } from 'src/service/prisma.service';
+import { TelegramService } from 'src/telegram/telegram.service';
+import { Prisma } from '../_generated/prisma/client';
 
 @Injectable()
 export class TrashService {
   private readonly logger = new Logger(TrashService.name);
-  constructor(private readonly prismaService: PrismaService) {}
+  constructor(
+    private readonly prismaService: PrismaService,
+    private readonly telegramService: TelegramService,
+  ) {}
 
   async createTextTrash({
     enc_trash_text,
     encryption_metadata,
     text_length,
     expire_at,
+    passcode_hash,
+    slot_passcode_hashes,
+    owner_token,
   }: CreateTextTrashDto) {
     if (!enc_trash_text || !encryption_metadata || !text_length) {
       throw new Error(
@@ -22,6 +32,8 @@ export class TrashService {
     }
 
     const slug = generateId('ts');
+    const passcodeHash =
+      passcode_hash ?? encryption_metadata.passcode_hash ?? '0000';
 
     try {
       await this.prismaService.trash.create({
@@ -29,9 +41,14 @@ export class TrashService {
           id: id('ts'),
           slug,
           type: 'TEXT',
-          encrypted: encryption_metadata.passcode_hash !== '0000',
-          passcodeHash: encryption_metadata.passcode_hash,
+          encrypted: passcodeHash !== '0000',
+          passcodeHash,
+          slotPasscodeHashes: slot_passcode_hashes ?? {},
+          ownerTokenHash: owner_token ? this.hashToken(owner_token) : null,
           expireAt: expire_at,
+          encryptionMetadata: this.publicMetadata(
+            encryption_metadata as Prisma.JsonObject,
+          ) as Prisma.InputJsonObject,
           textTrash: {
             create: {
               id: id('txt'),
@@ -56,12 +73,17 @@ export class TrashService {
     encryption_metadata,
     file_ids,
     message_ids,
+    passcode_hash,
+    slot_passcode_hashes,
+    owner_token,
   }: CreateFileTrashDto) {
     if (!encryption_metadata || !file_ids || file_ids.length === 0) {
       throw new Error('Missing required fields: encryption_metadata, file_ids');
     }
 
     const slug = generateId('ts');
+    const passcodeHash =
+      passcode_hash ?? encryption_metadata.passcode_hash ?? '0000';
 
     try {
       const trash = await this.prismaService.trash.create({
@@ -69,9 +91,14 @@ export class TrashService {
           id: id('ts'),
           slug,
           type: 'FILE',
-          encrypted: encryption_metadata.passcode_hash !== '0000',
-          passcodeHash: encryption_metadata.passcode_hash,
+          encrypted: passcodeHash !== '0000',
+          passcodeHash,
+          slotPasscodeHashes: slot_passcode_hashes ?? {},
+          ownerTokenHash: owner_token ? this.hashToken(owner_token) : null,
           expireAt: encryption_metadata.expire_at,
+          encryptionMetadata: this.publicMetadata(
+            encryption_metadata as Prisma.JsonObject,
+          ) as Prisma.InputJsonObject,
           fileTrash: {
             create: {
               id: id('ft'),
@@ -103,6 +130,211 @@ export class TrashService {
     }
   }
 
+  async updateEncryptionMetadata(
+    id: string,
+    {
+      owner_token,
+      passcode,
+      encryption_metadata,
+      slot_passcode_hashes,
+    }: UpdateEncryptionMetadataDto,
+  ) {
+    const key_slots = encryption_metadata?.key_slots;
+    if (!Array.isArray(key_slots) || !key_slots.length) {
+      throw new ErrorResponse(
+        'Missing required fields: encryption_metadata.key_slots',
+        400,
+      );
+    }
+
+    const trash = await this.prismaService.trash.findUnique({
+      where: { slug: id },
+    });
+
+    if (!trash) {
+      throw new ErrorResponse('Trash not found', 404);
+    }
+
+    if (!this.isOwner(trash, owner_token, passcode)) {
+      throw new ErrorResponse('Owner token is incorrect', 403);
+    }
+
+    // Kept slots keep their passcode hash, new and replaced ones bring theirs along
+    const known = {
+      ...this.slotPasscodeHashes(trash),
+      ...slot_passcode_hashes,
+    };
+    const slotIds = (key_slots as { id?: string }[]).map((slot) => slot?.id);
+    const slotPasscodeHashes: Record<string, string> = {};
+    for (const slotId of slotIds) {
+      if (slotId && known[slotId]) {
+        slotPasscodeHashes[slotId] = known[slotId];
+      }
+    }
+    // The first slot holds the main passcode
+    const passcodeHash =
+      slotPasscodeHashes[slotIds[0] ?? ''] ??
+      Object.values(slotPasscodeHashes)[0] ??
+      trash.passcodeHash;
+
+    // Anyone who can open the trash holds the owner token, so only the slots are taken
+    // from the request; the rest of the metadata stays as uploaded
+    const stored = this.publicMetadata({
+      ...(trash.encryptionMetadata as Prisma.JsonObject | null),
+      key_slots: key_slots as Prisma.JsonArray,
+    })!;
+    // Superseded by the key slots
+    delete stored.wrapped_key;
+    try {
+      await this.prismaService.trash.update({
+        where: { slug: id },
+        data: {
+          encrypted: passcodeHash !== '0000',
+          passcodeHash,
+          slotPasscodeHashes,
+          encryptionMetadata: stored as Prisma.InputJsonObject,
+        },
+      });
+    } catch (error) {
+      throw new ErrorResponse(
+        `DB: Failed to update trash metadata - ${error.message}`,
+        500,
+      );
+    }
+  }
+
+  async deleteTrash(
+    id: string,
+    ownerToken: string | undefined,
+    passcode: string | undefined,
+  ) {
+    const trash = await this.prismaService.trash.findUnique({
+      where: { slug: id },
+      include: { fileTrash: { select: { message_ids: true } } },
+    });
+
+    if (!trash) {
+      throw new ErrorResponse('Trash not found', 404);
+    }
+
+    if (!this.isOwner(trash, ownerToken, passcode)) {
+      throw new ErrorResponse('Owner token is incorrect', 403);
+    }
+
+    try {
+      await this.prismaService.trash.delete({ where: { slug: id } });
+    } catch (error) {
+      throw new ErrorResponse(
+        `DB: Failed to delete trash - ${error.message}`,
+        500,
+      );
+    }
+
+    // The chunks are ciphertext and the row is already gone, so a failure here
+    // only leaves unreachable messages behind
+    const messageIds = trash.fileTrash?.message_ids ?? [];
+    if (messageIds.length > 0) {
+      try {
+        await this.telegramService.deleteMessages(messageIds);
+      } catch (error) {
+        this.logger.warn(
+          `Failed to delete the Telegram messages of trash ${id}: ${error.message}`,
+        );
+      }
+    }
+  }
+
+  // Only the owner token changes or deletes a trash. Trashes created before owner
+  // tokens fall back to their passcode, so the public ones among them stay as they are
+  isOwner(
+    trash: {
+      encrypted: boolean;
+      passcodeHash: string;
+      ownerTokenHash: string | null;
+      slotPasscodeHashes: Prisma.JsonValue | null;
+      encryptionMetadata: Prisma.JsonValue | null;
+    },
+    ownerToken: string | undefined,
+    passcode: string | undefined,
+  ) {
+    if (!trash.ownerTokenHash) {
+      return trash.encrypted && this.isPasscodeValid(trash, passcode);
+    }
+    return (
+      !!ownerToken &&
+      timingSafeEqual(
+        Buffer.from(this.hashToken(ownerToken), 'hex'),
+        Buffer.from(trash.ownerTokenHash, 'hex'),
+      )
+    );
+  }
+
+  private hashToken(token: string) {
+    return createHash('sha256').update(token).digest('hex');
+  }
+
+  // A trash opens with its main passcode or with the passcode of any of its key slots
+  isPasscodeValid(
+    trash: {
+      encrypted: boolean;
+      passcodeHash: string;
+      slotPasscodeHashes: Prisma.JsonValue | null;
+      encryptionMetadata: Prisma.JsonValue | null;
+    },
+    passcode: string | undefined,
+  ) {
+    if (!trash.encrypted || trash.passcodeHash === passcode) {
+      return true;
+    }
+    return (
+      !!passcode &&
+      Object.values(this.slotPasscodeHashes(trash)).includes(passcode)
+    );
+  }
+
+  // Trashes stored before slotPasscodeHashes existed keep the hashes in their key slots
+  private slotPasscodeHashes(trash: {
+    slotPasscodeHashes: Prisma.JsonValue | null;
+    encryptionMetadata: Prisma.JsonValue | null;
+  }): Record<string, string> {
+    if (trash.slotPasscodeHashes) {
+      return trash.slotPasscodeHashes as Record<string, string>;
+    }
+    const metadata = trash.encryptionMetadata as {
+      key_slots?: { id?: string; passcode_hash?: string }[];
+    } | null;
+    const hashes: Record<string, string> = {};
+    for (const slot of metadata?.key_slots ?? []) {
+      if (slot?.id && slot.passcode_hash) {
+        hashes[slot.id] = slot.passcode_hash;
+      }
+    }
+    return hashes;
+  }
+
+  // Passcode hashes are unsalted and fast to guess, and they are what PATCH and DELETE
+  // check, so they never go into the metadata that is stored or handed out
+  publicMetadata(
+    metadata: Prisma.JsonValue | null,
+  ): Prisma.JsonObject | null {
+    if (!metadata || typeof metadata !== 'object' || Array.isArray(metadata)) {
+      return null;
+    }
+    const stripped: Prisma.JsonObject = { ...metadata };
+    delete stripped.passcode_hash;
+    if (Array.isArray(stripped.key_slots)) {
+      stripped.key_slots = stripped.key_slots.map((slot) => {
+        if (!slot || typeof slot !== 'object' || Array.isArray(slot)) {
+          return slot;
+        }
+        const publicSlot: Prisma.JsonObject = { ...slot };
+        delete publicSlot.passcode_hash;
+        return publicSlot;
+      });
+    }
+    return stripped;
+  }
+
   async findOne(id: string) {
     try {
       const trash = await this.prismaService.trash.findUnique({
@@ -0,0 +1,286 @@
+//! `tsbin`, the native command-line client. It shares the trash format with the browser
+//! through `tsbin-core`, so trashes created on either side open on the other.
+
+mod trash;
+
+use clap::{Parser, Subcommand};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+use trash::{SealOptions, Session, Unlock};
+use tsbin_core::{
+    Cipher, TsbinClient, backend::RustCrypto, legacy, rawkey, transport::NativeTransport,
+    types::Trash, utils,
+};
+use zeroize::Zeroizing;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+pub type Client = TsbinClient<NativeTransport>;
+
+#[derive(Parser)]
+#[command(
+    name = "tsbin",
+    version,
+    about = "End-to-end encrypted, expiring text and file sharing"
+)]
+struct Cli {
+    /// Base URL of the tsbin API
+    #[arg(long, env = "TSBIN_SERVER", default_value = "http://localhost:8000/v1")]
+    server: String,
+
+    #[arg(long, env = "TSBIN_TOKEN", default_value = "", hide_env_values = true)]
+    token: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// How to unlock a trash. Without either flag the passcode is prompted for when needed.
+#[derive(clap::Args)]
+struct UnlockArgs {
+    #[arg(
+        long,
+        env = "TSBIN_PASSCODE",
+        hide_env_values = true,
+        conflicts_with = "key"
+    )]
+    passcode: Option<String>,
+
+    /// File that is required alongside the passcode
+    #[arg(long, conflicts_with = "key")]
+    keyfile: Option<PathBuf>,
+
+    /// Raw 256-bit key, as base64 or JWK, instead of a passcode
+    #[arg(long)]
+    key: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt text from stdin, or a file, and print the trash id
+    Put {
+        /// File to upload; text is read from stdin when omitted
+        file: Option<PathBuf>,
+
+        #[command(flatten)]
+        unlock: UnlockArgs,
+
+        /// aes256gcm (the default) or xchacha20poly1305
+        #[arg(long)]
+        cipher: Option<String>,
+
+        /// Expiry date, as an ISO 8601 timestamp
+        #[arg(long)]
+        expire_at: Option<String>,
+    },
+    /// Decrypt a trash: text goes to stdout, files into the current directory
+    Get {
+        id: String,
+
+        #[command(flatten)]
+        unlock: UnlockArgs,
+
+        /// Where to write the content; `-` for stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Overwrite the output file if it exists
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Show the public details of a trash
+    Info { id: String },
+    /// Delete a trash; it is unlocked the same way as for `get`
+    Rm {
+        id: String,
+
+        #[command(flatten)]
+        unlock: UnlockArgs,
+    },
+    /// Decrypt a file written by the old Node CLI
+    Legacy {
+        file: PathBuf,
+
+        #[arg(long, env = "TSBIN_PASSCODE", hide_env_values = true)]
+        passcode: Option<String>,
+
+        /// Where to write the content; `-` for stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Overwrite the output file if it exists
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+impl UnlockArgs {
+    /// Builds the unlock, prompting for the passcode unless the trash is known to be public.
+    fn unlock(self, prompt: &str, encrypted: bool) -> Result<Unlock> {
+        if let Some(key) = self.key {
+            let key = Zeroizing::new(key);
+            return Ok(Unlock::Key(Zeroizing::new(rawkey::parse_key(&key)?)));
+        }
+        let keyfile_hash = match self.keyfile {
+            Some(path) => Some(utils::hash_keyfile(&Zeroizing::new(fs::read(path)?))),
+            None => None,
+        };
+        let passcode = match self.passcode {
+            Some(passcode) => Zeroizing::new(passcode),
+            None if encrypted => read_passcode(prompt)?,
+            None => Zeroizing::new(String::new()),
+        };
+        Ok(Unlock::Passcode(passcode, keyfile_hash))
+    }
+}
+
+fn read_passcode(prompt: &str) -> Result<Zeroizing<String>> {
+    Ok(Zeroizing::new(rpassword::prompt_password(prompt)?))
+}
+
+/// Writes decrypted content to `output`, or else to the stored file name in the current
+/// directory, or else to stdout.
+fn save(
+    content: &[u8],
+    output: Option<PathBuf>,
+    file_name: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    // Only the last path component of the stored name is trusted
+    let output = output
+        .or_else(|| file_name.and_then(|name| PathBuf::from(name).file_name().map(PathBuf::from)));
+    match output {
+        Some(path) if path.as_os_str() != "-" => {
+            if path.exists() && !force {
+                return Err(format!(
+                    "{} already exists; pass --force to overwrite it",
+                    path.display()
+                )
+                .into());
+            }
+            fs::write(&path, content)?;
+            eprintln!("Saved {}", path.display());
+        }
+        _ => io::stdout().write_all(content)?,
+    }
+    Ok(())
+}
+
+/// Looks up the type and object id behind a public trash id.
+async fn resolve(client: &Client, id: &str) -> Result<(Trash, String)> {
+    let trash = client.get_trash(id).await?;
+    let object_id = trash.object_id.clone().ok_or("Trash not found")?;
+    Ok((trash, object_id))
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let session = Session::new(
+        TsbinClient::new(cli.server, cli.token, NativeTransport::new()),
+        RustCrypto,
+    );
+    let client = session.client();
+
+    match cli.command {
+        Command::Put {
+            file,
+            unlock,
+            cipher,
+            expire_at,
+        } => {
+            let unlock = unlock.unlock("Passcode (empty for none): ", true)?;
+            let options = SealOptions {
+                cipher: cipher
+                    .as_deref()
+                    .map_or(Ok(Cipher::default()), Cipher::parse)?,
+                expire_at,
+                chunk_size: trash::DEFAULT_CHUNK_SIZE,
+            };
+            let trash_id = match file {
+                Some(path) => trash::put_file(&session, &path, &unlock, &options).await?,
+                None => {
+                    let mut content = Zeroizing::new(Vec::new());
+                    io::stdin().read_to_end(&mut content)?;
+                    let content =
+                        Zeroizing::new(String::from_utf8(content.to_vec()).map_err(
+                            |_| "Text trashes must be UTF-8; upload binary data as a file",
+                        )?);
+                    trash::put_text(&session, &content, &unlock, &options).await?
+                }
+            };
+            println!("{}", trash_id);
+        }
+        Command::Get {
+            id,
+            unlock,
+            output,
+            force,
+        } => {
+            let (trash, object_id) = resolve(client, &id).await?;
+            let unlock = unlock.unlock("Passcode: ", trash.encrypted)?;
+            let opened = match trash.trash_type.as_str() {
+                "TEXT" => trash::get_text(&session, &object_id, &unlock).await?,
+                "FILE" => trash::get_file(&session, &object_id, &unlock).await?,
+                _ => return Err("Unknown trash type".into()),
+            };
+            if let Some(signer) = &opened.signer {
+                eprintln!("Signed by {}", signer);
+            }
+
+            save(&opened.content, output, opened.file_name.as_deref(), force)?;
+        }
+        Command::Info { id } => {
+            let (trash, object_id) = resolve(client, &id).await?;
+            let or_dash = |value: &Option<String>| value.as_deref().unwrap_or("-").to_string();
+            println!("id:        {}", trash.slug);
+            println!("type:      {}", trash.trash_type);
+            println!("encrypted: {}", trash.encrypted);
+            println!("created:   {}", or_dash(&trash.created_at));
+            println!("expires:   {}", or_dash(&trash.expire_at));
+            if trash.trash_type == "FILE" {
+                let meta = client.get_file_trash_meta(&object_id).await?;
+                println!("file:      {}", meta.file_name);
+                println!("mime type: {}", meta.mime_type);
+                println!("size:      {}", meta.file_size);
+            }
+        }
+        Command::Rm { id, unlock } => {
+            let (trash, _) = resolve(client, &id).await?;
+            let unlock = unlock.unlock("Passcode: ", trash.encrypted)?;
+            trash::delete(&session, &id, &unlock).await?;
+            eprintln!("Deleted {}", id);
+        }
+        Command::Legacy {
+            file,
+            passcode,
+            output,
+            force,
+        } => {
+            let data = fs::read(&file)?;
+            if !legacy::is_legacy(&data) {
+                return Err("Not a file written by the Node CLI".into());
+            }
+            let passcode = match passcode {
+                Some(passcode) => Zeroizing::new(passcode),
+                None => read_passcode("Passcode: ")?,
+            };
+            let opened = legacy::decrypt(&data, &passcode)?;
+            let content = Zeroizing::new(opened.content);
+            save(&content, output, Some(&opened.file_name), force)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    // The native transport blocks, so every future finishes on its first poll
+    match futures::executor::block_on(run(Cli::parse())) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
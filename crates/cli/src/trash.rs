@@ -0,0 +1,385 @@
+//! Seals and opens trashes through `tsbin_core::trash::Session`, the code `TsbinController`
+//! runs in the browser, so either side can open what the other created.
+
+use crate::Result;
+use std::{fs::File, io::Read, path::Path};
+use tsbin_core::{
+    Cipher, Error,
+    backend::RustCrypto,
+    keywrap,
+    transport::Transport,
+    trash::{self, ChunkSource, KeySource, NewFile},
+    types::UploadProgress,
+};
+use zeroize::Zeroizing;
+
+pub use tsbin_core::trash::DEFAULT_CHUNK_SIZE;
+
+pub type Session<T> = trash::Session<T, RustCrypto>;
+
+/// How the caller proves access to a trash's data key.
+pub enum Unlock {
+    /// A passcode, together with the hash of the keyfile for trashes that require one
+    Passcode(Zeroizing<String>, Option<[u8; 32]>),
+    /// The caller-managed raw data key
+    Key(Zeroizing<[u8; 32]>),
+}
+
+pub struct SealOptions {
+    pub cipher: Cipher,
+    pub expire_at: Option<String>,
+    pub chunk_size: usize,
+}
+
+/// A decrypted trash, with the signer fingerprint of signed ones.
+pub struct Opened {
+    pub content: Zeroizing<Vec<u8>>,
+    pub file_name: Option<String>,
+    pub signer: Option<String>,
+}
+
+impl Unlock {
+    fn as_trash_unlock(&self) -> trash::Unlock<'_> {
+        match self {
+            Unlock::Passcode(passcode, keyfile_hash) => {
+                trash::Unlock::Passcode(passcode, *keyfile_hash)
+            }
+            Unlock::Key(key) => trash::Unlock::Key(key),
+        }
+    }
+
+    /// The data key of a new trash: freshly generated unless the caller brings their own.
+    fn data_key(&self) -> Zeroizing<[u8; 32]> {
+        match self {
+            Unlock::Passcode(..) => Zeroizing::new(keywrap::generate_data_key()),
+            Unlock::Key(key) => key.clone(),
+        }
+    }
+}
+
+impl SealOptions {
+    fn for_unlock<'a>(&'a self, unlock: &'a Unlock) -> trash::SealOptions<'a> {
+        let (passcode, keyfile_hash, key_source) = match unlock {
+            Unlock::Passcode(passcode, keyfile_hash) => {
+                (Some(passcode.as_str()), *keyfile_hash, KeySource::Random)
+            }
+            Unlock::Key(_) => (None, None, KeySource::Raw),
+        };
+        trash::SealOptions {
+            cipher: self.cipher,
+            expire_at: self.expire_at.as_deref(),
+            chunk_size: self.chunk_size,
+            passcode,
+            keyfile_hash,
+            key_source,
+            ..trash::SealOptions::default()
+        }
+    }
+}
+
+/// A file on disk, read one chunk at a time.
+struct FileSource {
+    file: File,
+    size: usize,
+    position: usize,
+}
+
+impl ChunkSource for FileSource {
+    type Chunk = Zeroizing<Vec<u8>>;
+
+    fn size(&self) -> Option<usize> {
+        Some(self.size)
+    }
+
+    async fn next_chunk(&mut self, chunk_size: usize) -> tsbin_core::Result<Option<Self::Chunk>> {
+        let len = std::cmp::min(chunk_size, self.size - self.position);
+        if len == 0 {
+            return Ok(None);
+        }
+        let mut chunk = Zeroizing::new(vec![0; len]);
+        self.file
+            .read_exact(&mut chunk)
+            .map_err(|e| Error::Backend(e.to_string()))?;
+        self.position += len;
+        Ok(Some(chunk))
+    }
+}
+
+/// Reports every chunk that went through on stderr.
+fn report(verb: &'static str) -> impl FnMut(&UploadProgress) -> tsbin_core::Result<()> {
+    let mut reported = 0;
+    move |progress| {
+        if progress.uploaded_chunks > reported {
+            reported = progress.uploaded_chunks;
+            eprintln!(
+                "{} chunk {}/{}",
+                verb, progress.uploaded_chunks, progress.total_chunks
+            );
+        }
+        Ok(())
+    }
+}
+
+pub async fn put_text<T: Transport>(
+    session: &Session<T>,
+    content: &str,
+    unlock: &Unlock,
+    options: &SealOptions,
+) -> Result<String> {
+    let data_key = unlock.data_key();
+    let options = options.for_unlock(unlock);
+    Ok(session.put_text(content, &data_key, &options).await?)
+}
+
+/// Encrypts `path` chunk by chunk and uploads every chunk with retries, reporting
+/// progress on stderr.
+pub async fn put_file<T: Transport>(
+    session: &Session<T>,
+    path: &Path,
+    unlock: &Unlock,
+    options: &SealOptions,
+) -> Result<String> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len() as usize;
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Invalid file name")?;
+    let mime_type = mime_guess::from_path(path).first_or_octet_stream();
+    let file = NewFile {
+        name: name.to_string(),
+        mime_type: mime_type.essence_str().to_string(),
+        source: FileSource {
+            file,
+            size,
+            position: 0,
+        },
+    };
+
+    let data_key = unlock.data_key();
+    let options = options.for_unlock(unlock);
+    Ok(session
+        .put_file(file, &data_key, &options, report("Uploaded"))
+        .await?)
+}
+
+pub async fn get_text<T: Transport>(
+    session: &Session<T>,
+    object_id: &str,
+    unlock: &Unlock,
+) -> Result<Opened> {
+    let opened = session
+        .open_text(object_id, &unlock.as_trash_unlock())
+        .await?;
+    Ok(Opened {
+        content: opened.content,
+        file_name: None,
+        signer: opened.signer,
+    })
+}
+
+/// Downloads and decrypts every chunk of a file trash, checking the result against
+/// the manifest before handing it out.
+pub async fn get_file<T: Transport>(
+    session: &Session<T>,
+    object_id: &str,
+    unlock: &Unlock,
+) -> Result<Opened> {
+    let opened = session
+        .open_file(object_id, &unlock.as_trash_unlock(), report("Downloaded"))
+        .await?;
+    Ok(Opened {
+        content: opened.content,
+        file_name: Some(opened.file_name),
+        signer: opened.signer,
+    })
+}
+
+/// Deletes a trash. It is opened first, for the owner token the server asks for.
+pub async fn delete<T: Transport>(
+    session: &Session<T>,
+    trash_id: &str,
+    unlock: &Unlock,
+) -> Result<()> {
+    Ok(session.delete(trash_id, &unlock.as_trash_unlock()).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::{ops::Deref, path::PathBuf};
+    use tsbin_core::{
+        mock::{
+            Fault, MockServer,
+            fixtures::{self, content, object_id},
+        },
+        utils,
+    };
+
+    fn session() -> Session<MockServer> {
+        fixtures::session(RustCrypto)
+    }
+
+    fn passcode(passcode: &str) -> Unlock {
+        Unlock::Passcode(Zeroizing::new(passcode.to_string()), None)
+    }
+
+    fn options(chunk_size: usize) -> SealOptions {
+        SealOptions {
+            cipher: Cipher::default(),
+            expire_at: None,
+            chunk_size,
+        }
+    }
+
+    /// A file in a directory of its own under the temporary directory, both removed on
+    /// drop so a failing test leaves nothing behind either.
+    struct TempFile(PathBuf);
+
+    impl Deref for TempFile {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.parent() {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    /// Writes `content` to a file of its own under the temporary directory.
+    fn temp_file(name: &str, content: &[u8]) -> TempFile {
+        let dir = std::env::temp_dir().join(format!("tsbin-{}", utils::generate_uuid()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = TempFile(dir.join(name));
+        std::fs::write(&file.0, content).unwrap();
+        file
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let session = session();
+        let client = session.client();
+        let slug = block_on(put_text(&session, "hello", &passcode("pw"), &options(0))).unwrap();
+        let object_id = block_on(object_id(client, &slug));
+
+        let opened = block_on(get_text(&session, &object_id, &passcode("pw"))).unwrap();
+        assert_eq!(&opened.content[..], b"hello");
+        assert!(block_on(get_text(&session, &object_id, &passcode("wrong"))).is_err());
+    }
+
+    #[test]
+    fn raw_key_text_opens_only_with_the_key() {
+        let session = session();
+        let client = session.client();
+        let key = Unlock::Key(Zeroizing::new([7; 32]));
+        let slug = block_on(put_text(&session, "hello", &key, &options(0))).unwrap();
+        let object_id = block_on(object_id(client, &slug));
+
+        assert!(block_on(get_text(&session, &object_id, &passcode(""))).is_err());
+        let opened = block_on(get_text(&session, &object_id, &key)).unwrap();
+        assert_eq!(&opened.content[..], b"hello");
+    }
+
+    #[test]
+    fn file_round_trip_over_several_chunks() {
+        let session = session();
+        let client = session.client();
+        client.transport().inject(Fault::Delay(3));
+        let data = content(3500);
+        let path = temp_file("notes.txt", &data);
+        let slug = block_on(put_file(&session, &path, &passcode("pw"), &options(1000))).unwrap();
+        assert_eq!(client.transport().chunk_count(), 4);
+
+        let object_id = block_on(object_id(client, &slug));
+        let opened = block_on(get_file(&session, &object_id, &passcode("pw"))).unwrap();
+        assert_eq!(&opened.content[..], &data[..]);
+        assert_eq!(opened.file_name.as_deref(), Some("notes.txt"));
+        let metadata = client.transport().metadata(&slug).unwrap();
+        assert_eq!(metadata["mime_type"], "text/plain");
+        assert_eq!(metadata["total_chunks"], 4);
+    }
+
+    #[test]
+    fn dropped_chunks_are_retried() {
+        let session = session();
+        let client = session.client();
+        client.transport().inject(Fault::DropChunk(1));
+        client.transport().inject(Fault::DropChunk(1));
+        let data = content(2500);
+        let path = temp_file("data.bin", &data);
+        let slug = block_on(put_file(&session, &path, &passcode("pw"), &options(1000))).unwrap();
+
+        let object_id = block_on(object_id(client, &slug));
+        let opened = block_on(get_file(&session, &object_id, &passcode("pw"))).unwrap();
+        assert_eq!(&opened.content[..], &data[..]);
+    }
+
+    #[test]
+    fn upload_gives_up_after_repeated_server_errors() {
+        let session = session();
+        let client = session.client();
+        for _ in 0..trash::DEFAULT_MAX_RETRIES {
+            client
+                .transport()
+                .inject(Fault::ServerError("/trash/chunk"));
+        }
+        let path = temp_file("data.bin", &content(10));
+        let error = block_on(put_file(&session, &path, &passcode("pw"), &options(1000)))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Failed to upload chunk 0 after 3 retries: HTTP error: 500 (Internal server error)"
+        );
+    }
+
+    #[test]
+    fn reordered_chunks_are_rejected() {
+        let session = session();
+        let client = session.client();
+        let path = temp_file("data.bin", &content(2500));
+        let slug = block_on(put_file(&session, &path, &passcode("pw"), &options(1000))).unwrap();
+
+        let object_id = block_on(object_id(client, &slug));
+        client.transport().inject(Fault::ReorderChunks);
+        assert!(block_on(get_file(&session, &object_id, &passcode("pw"))).is_err());
+    }
+
+    #[test]
+    fn tampered_chunks_are_rejected() {
+        let session = session();
+        let client = session.client();
+        let path = temp_file("data.bin", &content(2500));
+        let slug = block_on(put_file(&session, &path, &passcode("pw"), &options(1000))).unwrap();
+        let object_id = block_on(object_id(client, &slug));
+
+        let meta = block_on(client.get_file_trash_meta(&object_id)).unwrap();
+        let file_ids = meta.file_ids.unwrap();
+        let mut chunk = block_on(client.download_chunk(&object_id, &file_ids[0])).unwrap();
+        let last = chunk.len() - 1;
+        chunk[last] ^= 1;
+        client.transport().tamper_chunk(&file_ids[0], chunk);
+        assert!(block_on(get_file(&session, &object_id, &passcode("pw"))).is_err());
+    }
+
+    #[test]
+    fn rm_needs_what_opens_the_trash() {
+        let session = session();
+        let key = Unlock::Key(Zeroizing::new([7; 32]));
+        let slug = block_on(put_text(&session, "hello", &key, &options(0))).unwrap();
+
+        // Anyone can fetch the trash, but only the key derives its owner token
+        assert!(block_on(delete(&session, &slug, &passcode(""))).is_err());
+        let wrong = Unlock::Key(Zeroizing::new([8; 32]));
+        assert!(block_on(delete(&session, &slug, &wrong)).is_err());
+        block_on(delete(&session, &slug, &key)).unwrap();
+        assert!(block_on(session.client().get_trash(&slug)).is_err());
+    }
+}
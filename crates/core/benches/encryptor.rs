@@ -0,0 +1,136 @@
+//! Compares the in-place `Encryptor` paths with the allocate-per-segment approach they
+//! replaced, on a 100MB input. Peak heap use of each path is printed before timing.
+//!
+//!     cargo bench -p tsbin-core --bench encryptor
+
+use aes_gcm::{
+    Aes256Gcm,
+    aead::{Aead, KeyInit},
+};
+use criterion::{BatchSize, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tsbin_core::Encryptor;
+
+const INPUT_SIZE: usize = 100 * 1024 * 1024;
+const CHUNK_SIZE: usize = 5 * 1024 * 1024;
+const KEY: [u8; 32] = [7; 32];
+
+/// Tracks live and peak heap bytes.
+struct CountingAlloc;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Heap growth at the peak of `f`, on top of what was live before it ran.
+fn peak_during<T>(f: impl FnOnce() -> T) -> usize {
+    let before = LIVE.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    drop(f());
+    PEAK.load(Ordering::Relaxed) - before
+}
+
+/// The previous encrypt path: the input is copied out of JS memory, then every segment is
+/// sealed into a fresh buffer and appended to a growing output.
+fn naive_encrypt(data: &[u8]) -> Vec<u8> {
+    let data = data.to_vec();
+    let cipher = Aes256Gcm::new(&KEY.into());
+    let mut output = Vec::new();
+    for (index, chunk) in (0u64..).zip(data.chunks(CHUNK_SIZE)) {
+        let mut nonce = [0u8; 12];
+        nonce[..8].copy_from_slice(&index.to_be_bytes());
+        let ciphertext = cipher.encrypt(&nonce.into(), chunk).unwrap();
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+        output.extend_from_slice(&ciphertext);
+    }
+    output
+}
+
+/// The previous decrypt path: every segment is opened into a fresh buffer and appended.
+fn naive_decrypt(data: &[u8]) -> Vec<u8> {
+    let cipher = Aes256Gcm::new(&KEY.into());
+    let mut output = Vec::with_capacity(data.len());
+    let mut cursor = 0;
+    while cursor < data.len() {
+        let nonce: [u8; 12] = data[cursor..cursor + 12].try_into().unwrap();
+        let len = u32::from_be_bytes(data[cursor + 12..cursor + 16].try_into().unwrap()) as usize;
+        cursor += 16;
+        let plaintext = cipher
+            .decrypt(&nonce.into(), &data[cursor..cursor + len])
+            .unwrap();
+        output.extend_from_slice(&plaintext);
+        cursor += len;
+    }
+    output
+}
+
+fn report_peak_memory(input: &[u8]) {
+    let encryptor = Encryptor::from_raw_key(&KEY).unwrap();
+    let naive_sealed = naive_encrypt(input);
+    let sealed = encryptor.encrypt(input);
+
+    let mb = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    println!("peak heap over a {}MB input:", INPUT_SIZE / (1024 * 1024));
+    println!(
+        "  encrypt  naive {:>7.1}MB  in place {:>7.1}MB",
+        mb(peak_during(|| naive_encrypt(input))),
+        mb(peak_during(|| encryptor.encrypt(input)))
+    );
+    println!(
+        "  decrypt  naive {:>7.1}MB  in place {:>7.1}MB",
+        mb(peak_during(|| naive_decrypt(&naive_sealed))),
+        // The in-place path consumes its input, so the copy handed to it is counted too
+        mb(peak_during(|| encryptor.decrypt(sealed.clone()).unwrap()))
+    );
+}
+
+fn bench_encryptor(c: &mut Criterion, input: &[u8]) {
+    let encryptor = Encryptor::from_raw_key(&KEY).unwrap();
+    let naive_sealed = naive_encrypt(input);
+    let sealed = encryptor.encrypt(input);
+
+    let mut group = c.benchmark_group("encryptor");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(INPUT_SIZE as u64));
+
+    group.bench_function("encrypt/naive", |b| b.iter(|| naive_encrypt(input)));
+    group.bench_function("encrypt/in_place", |b| b.iter(|| encryptor.encrypt(input)));
+    group.bench_function("decrypt/naive", |b| b.iter(|| naive_decrypt(&naive_sealed)));
+    group.bench_function("decrypt/in_place", |b| {
+        b.iter_batched(
+            || sealed.clone(),
+            |sealed| encryptor.decrypt(sealed).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn main() {
+    let input: Vec<u8> = (0..INPUT_SIZE).map(|i| i as u8).collect();
+    report_peak_memory(&input);
+
+    let mut criterion = Criterion::default().configure_from_args();
+    bench_encryptor(&mut criterion, &input);
+    criterion.final_summary();
+}
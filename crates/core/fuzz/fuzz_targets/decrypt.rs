@@ -0,0 +1,39 @@
+#![no_main]
+//! The segment parser of `Encryptor`, in place and through a `CryptoBackend`, which must
+//! agree on every input.
+
+use libfuzzer_sys::fuzz_target;
+use tsbin_core::{
+    Cipher, Encryptor,
+    backend::{RustCrypto, run_sync},
+};
+
+const KEY: [u8; 32] = [7; 32];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, data)) = data.split_first() else {
+        return;
+    };
+    let cipher = match flags & 1 {
+        0 => Cipher::Aes256Gcm,
+        _ => Cipher::XChaCha20Poly1305,
+    };
+    let encryptor = || Encryptor::from_key(KEY).with_cipher(cipher);
+
+    // A valid key commitment in front gets the input past the header to the segments
+    let mut ciphertext = match flags & 2 {
+        0 => Vec::new(),
+        _ => encryptor().encrypt(&[]),
+    };
+    ciphertext.extend_from_slice(data);
+
+    let in_place = encryptor().decrypt(ciphertext.clone());
+    let segments = run_sync(encryptor().decrypt_segments(&RustCrypto, &ciphertext));
+    assert_eq!(in_place.ok(), segments.ok());
+
+    let sealed = encryptor().encrypt(data);
+    assert_eq!(
+        encryptor().require_commitment().decrypt(sealed).unwrap(),
+        data
+    );
+});
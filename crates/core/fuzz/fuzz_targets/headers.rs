@@ -0,0 +1,48 @@
+#![no_main]
+//! Everything that parses a header or an encoded key: age and legacy Node CLI files,
+//! key shares, raw keys, recipient and signing keys and exported derived keys.
+
+use libfuzzer_sys::fuzz_target;
+use tsbin_core::{
+    DerivedKey,
+    age::{self, AgeIdentity},
+    legacy, rawkey, recipient,
+    shamir::Share,
+    signing,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, data)) = data.split_first() else {
+        return;
+    };
+    match flags % 3 {
+        0 => {
+            // Passphrases would run scrypt with a work factor taken from the input
+            let mut file = b"age-encryption.org/v1\n".to_vec();
+            file.extend_from_slice(data);
+            let identity = AgeIdentity::X25519(
+                recipient::parse_secret_key(
+                    "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX",
+                )
+                .unwrap(),
+            );
+            let _ = age::decrypt(&file, &identity);
+        }
+        1 => {
+            if legacy::is_legacy(data) {
+                let _ = legacy::decrypt(data, "passphrase");
+            }
+        }
+        _ => {
+            let Ok(text) = std::str::from_utf8(data) else {
+                return;
+            };
+            let _ = Share::decode(text);
+            let _ = rawkey::parse_key(text);
+            let _ = recipient::parse_public_key(text);
+            let _ = recipient::parse_secret_key(text);
+            let _ = signing::parse_verifying_key(text);
+            let _ = DerivedKey::import(text);
+        }
+    }
+});
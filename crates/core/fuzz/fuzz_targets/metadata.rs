@@ -0,0 +1,34 @@
+#![no_main]
+//! Encryption metadata and the trash models, as the server hands them out.
+
+use libfuzzer_sys::fuzz_target;
+use serde_json::Value;
+use tsbin_core::{
+    Manifest, keywrap, manifest, recipient, signing,
+    types::{ApiResponse, ChunkUploadResponse, EncryptedText, Trash, TrashMeta},
+};
+
+const KEY: [u8; 32] = [7; 32];
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(metadata) = serde_json::from_slice::<Value>(data) else {
+        return;
+    };
+    let _ = serde_json::from_value::<TrashMeta>(metadata.clone());
+    let _ = serde_json::from_value::<ChunkUploadResponse>(metadata.clone());
+    let _ = serde_json::from_value::<ApiResponse<Trash>>(metadata.clone());
+    let _ = serde_json::from_value::<ApiResponse<EncryptedText>>(metadata.clone());
+
+    let chunk_hashes: Vec<String> =
+        serde_json::from_value(metadata["chunk_hashes"].clone()).unwrap_or_default();
+    let _ = keywrap::key_slots(&metadata);
+    if let Ok(slots) = recipient::recipient_slots(&metadata) {
+        let _ = recipient::open_recipient_slots(
+            &slots,
+            "AGE-SECRET-KEY-1GFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPYYSJZGFPQ4EGAEX",
+        );
+    }
+    let _ = Manifest::new(&metadata, chunk_hashes.clone()).to_bytes();
+    let _ = manifest::verify(Some(&metadata), &chunk_hashes, &KEY);
+    let _ = signing::verify(Some(&metadata), chunk_hashes);
+});
@@ -0,0 +1,63 @@
+#![no_main]
+//! Every `TsbinClient` call against an arbitrary response, error statuses included.
+
+use futures::executor::block_on;
+use libfuzzer_sys::fuzz_target;
+use serde_json::json;
+use std::collections::BTreeMap;
+use tsbin_core::{
+    Result, TsbinClient,
+    transport::{Request, Response, Transport},
+    types::TrashAccess,
+};
+
+/// Answers every request with the same response.
+struct Replay {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl Transport for Replay {
+    async fn send(&self, _request: Request) -> Result<Response> {
+        Ok(Response {
+            status: self.status,
+            body: self.body.clone(),
+        })
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((status, body)) = data.split_first_chunk::<2>() else {
+        return;
+    };
+    let client = TsbinClient::new(
+        "http://localhost/v1".to_string(),
+        String::new(),
+        Replay {
+            status: u16::from_be_bytes(*status) % 600,
+            body: body.to_vec(),
+        },
+    );
+    let metadata = json!({});
+    let access = TrashAccess {
+        passcode_hash: "0000".to_string(),
+        slot_passcode_hashes: BTreeMap::new(),
+        owner_token: None,
+    };
+
+    let _ = block_on(client.upload_text("", &metadata, &access));
+    let _ = block_on(client.get_file_trash_meta("id"));
+    let _ = block_on(client.get_text_obj("id", "0000"));
+    let _ = block_on(client.get_trash("id"));
+    let _ = block_on(client.update_encryption_metadata(
+        "id",
+        Some("token"),
+        "0000",
+        &metadata,
+        &BTreeMap::new(),
+    ));
+    let _ = block_on(client.delete_trash("id", Some("token"), "0000"));
+    let _ = block_on(client.upload_chunk(b"", 0));
+    let _ = block_on(client.create_file_trash(Vec::new(), Vec::new(), &metadata, &access));
+    let _ = block_on(client.download_chunk("id", "id"));
+});
@@ -0,0 +1,419 @@
+//! Encoding and decoding of the age v1 file format (https://age-encryption.org/v1), so trashes
+//! can be opened with the standard `age` tool and existing `.age` files can be uploaded.
+//! Only the binary format is supported, not the ASCII armor.
+
+use crate::{
+    error::{Error, Result},
+    utils,
+};
+use base64::{Engine, engine::general_purpose::STANDARD_NO_PAD};
+use chacha20poly1305::{
+    ChaCha20Poly1305,
+    aead::{Aead, KeyInit},
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const VERSION_LINE: &[u8] = b"age-encryption.org/v1\n";
+const X25519_LABEL: &[u8] = b"age-encryption.org/v1/X25519";
+const SCRYPT_LABEL: &[u8] = b"age-encryption.org/v1/scrypt";
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const BODY_COLUMNS: usize = 64;
+pub const DEFAULT_WORK_FACTOR: u8 = 18;
+// scrypt with r = 8 needs 2^(work factor + 10) bytes, more than a browser tab should allocate past 256 MiB
+const MAX_WORK_FACTOR: u8 = 18;
+
+pub enum AgeRecipient {
+    Scrypt { passphrase: String, work_factor: u8 },
+    X25519(PublicKey),
+}
+
+pub enum AgeIdentity<'a> {
+    Passphrase(&'a str),
+    X25519(StaticSecret),
+}
+
+struct Stanza {
+    tag: String,
+    args: Vec<String>,
+    body: Vec<u8>,
+}
+
+struct Header<'a> {
+    stanzas: Vec<Stanza>,
+    /// Everything up to and including `---`, which the header MAC covers
+    mac_input: &'a [u8],
+    mac: Vec<u8>,
+    payload: &'a [u8],
+}
+
+pub fn is_age(data: &[u8]) -> bool {
+    data.starts_with(VERSION_LINE)
+}
+
+fn hkdf(ikm: &[u8], salt: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, &mut key)
+        .expect("valid HKDF output length");
+    key
+}
+
+fn seal_file_key(wrap_key: &[u8; 32], file_key: &[u8; 16]) -> Vec<u8> {
+    ChaCha20Poly1305::new(wrap_key.into())
+        .encrypt(&[0u8; 12].into(), file_key.as_slice())
+        .expect("encryption failure")
+}
+
+fn open_file_key(wrap_key: &[u8; 32], body: &[u8]) -> Option<[u8; 16]> {
+    ChaCha20Poly1305::new(wrap_key.into())
+        .decrypt(&[0u8; 12].into(), body)
+        .ok()?
+        .try_into()
+        .ok()
+}
+
+fn scrypt_key(passphrase: &str, salt: &[u8], work_factor: u8) -> Result<[u8; 32]> {
+    if work_factor > MAX_WORK_FACTOR {
+        return Err(Error::invalid("scrypt work factor is too large"));
+    }
+    let mut full_salt = SCRYPT_LABEL.to_vec();
+    full_salt.extend_from_slice(salt);
+    let params =
+        scrypt::Params::new(work_factor, 8, 1, 32).map_err(|e| Error::invalid(e.to_string()))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), &full_salt, &params, &mut key)
+        .map_err(|e| Error::Backend(e.to_string()))?;
+    Ok(key)
+}
+
+fn wrap_file_key(recipient: &AgeRecipient, file_key: &[u8; 16]) -> Result<Stanza> {
+    match recipient {
+        AgeRecipient::Scrypt {
+            passphrase,
+            work_factor,
+        } => {
+            let salt = utils::random_bytes::<16>();
+            let key = scrypt_key(passphrase, &salt, *work_factor)?;
+            Ok(Stanza {
+                tag: "scrypt".to_string(),
+                args: vec![STANDARD_NO_PAD.encode(salt), work_factor.to_string()],
+                body: seal_file_key(&key, file_key),
+            })
+        }
+        AgeRecipient::X25519(public_key) => {
+            let ephemeral_secret = StaticSecret::from(utils::random_bytes::<32>());
+            let ephemeral = PublicKey::from(&ephemeral_secret);
+            let shared = ephemeral_secret.diffie_hellman(public_key);
+            if !shared.was_contributory() {
+                return Err(Error::format("Invalid recipient key"));
+            }
+            let mut salt = ephemeral.as_bytes().to_vec();
+            salt.extend_from_slice(public_key.as_bytes());
+            let key = hkdf(shared.as_bytes(), &salt, X25519_LABEL);
+            Ok(Stanza {
+                tag: "X25519".to_string(),
+                args: vec![STANDARD_NO_PAD.encode(ephemeral.as_bytes())],
+                body: seal_file_key(&key, file_key),
+            })
+        }
+    }
+}
+
+fn unwrap_file_key(stanza: &Stanza, identity: &AgeIdentity) -> Result<Option<[u8; 16]>> {
+    let decode = |value: &str| STANDARD_NO_PAD.decode(value);
+    match (stanza.tag.as_str(), identity) {
+        ("scrypt", AgeIdentity::Passphrase(passphrase)) => {
+            let [salt, work_factor] = stanza.args.as_slice() else {
+                return Err(Error::format("Invalid scrypt stanza"));
+            };
+            let salt = decode(salt)?;
+            let work_factor: u8 = work_factor
+                .parse()
+                .map_err(|_| Error::format("Invalid scrypt stanza"))?;
+            if salt.len() != 16 || work_factor == 0 {
+                return Err(Error::format("Invalid scrypt stanza"));
+            }
+            let key = scrypt_key(passphrase, &salt, work_factor)?;
+            Ok(open_file_key(&key, &stanza.body))
+        }
+        ("X25519", AgeIdentity::X25519(secret)) => {
+            let [ephemeral] = stanza.args.as_slice() else {
+                return Err(Error::format("Invalid X25519 stanza"));
+            };
+            let ephemeral: [u8; 32] = decode(ephemeral)?
+                .try_into()
+                .map_err(|_| Error::format("Invalid X25519 stanza"))?;
+            let ephemeral = PublicKey::from(ephemeral);
+            let shared = secret.diffie_hellman(&ephemeral);
+            if !shared.was_contributory() {
+                return Err(Error::format("Invalid X25519 stanza"));
+            }
+            let mut salt = ephemeral.as_bytes().to_vec();
+            salt.extend_from_slice(PublicKey::from(secret).as_bytes());
+            let key = hkdf(shared.as_bytes(), &salt, X25519_LABEL);
+            Ok(open_file_key(&key, &stanza.body))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn header_mac(file_key: &[u8; 16], header: &[u8]) -> Hmac<Sha256> {
+    let mac_key = hkdf(file_key, &[], b"header");
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(&mac_key).expect("HMAC accepts any key length");
+    mac.update(header);
+    mac
+}
+
+fn stream_nonce(counter: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+pub fn encrypt(plaintext: &[u8], recipients: &[AgeRecipient]) -> Result<Vec<u8>> {
+    if recipients.is_empty() {
+        return Err(Error::invalid("No age recipients"));
+    }
+    if recipients.len() > 1
+        && recipients
+            .iter()
+            .any(|recipient| matches!(recipient, AgeRecipient::Scrypt { .. }))
+    {
+        return Err(Error::invalid(
+            "A passphrase cannot be combined with other age recipients",
+        ));
+    }
+
+    let file_key = utils::random_bytes::<16>();
+    let mut output = VERSION_LINE.to_vec();
+    for recipient in recipients {
+        let stanza = wrap_file_key(recipient, &file_key)?;
+        output
+            .extend_from_slice(format!("-> {} {}\n", stanza.tag, stanza.args.join(" ")).as_bytes());
+        let body = STANDARD_NO_PAD.encode(&stanza.body);
+        // The body is wrapped at 64 columns and always ends with a line shorter than that
+        for line in body.as_bytes().chunks(BODY_COLUMNS) {
+            output.extend_from_slice(line);
+            output.push(b'\n');
+        }
+        if body.len() % BODY_COLUMNS == 0 {
+            output.push(b'\n');
+        }
+    }
+    output.extend_from_slice(b"---");
+    let mac = header_mac(&file_key, &output).finalize().into_bytes();
+    output.extend_from_slice(format!(" {}\n", STANDARD_NO_PAD.encode(mac)).as_bytes());
+
+    let nonce = utils::random_bytes::<16>();
+    output.extend_from_slice(&nonce);
+    let payload_key = hkdf(&file_key, &nonce, b"payload");
+    let cipher = ChaCha20Poly1305::new(&payload_key.into());
+
+    let chunk_count = plaintext.len().div_ceil(STREAM_CHUNK_SIZE).max(1);
+    for counter in 0..chunk_count {
+        let start = counter * STREAM_CHUNK_SIZE;
+        let end = std::cmp::min(start + STREAM_CHUNK_SIZE, plaintext.len());
+        let nonce = stream_nonce(counter as u64, counter + 1 == chunk_count);
+        let ciphertext = cipher
+            .encrypt(&nonce.into(), &plaintext[start..end])
+            .expect("encryption failure");
+        output.extend_from_slice(&ciphertext);
+    }
+    Ok(output)
+}
+
+fn parse_header(data: &[u8]) -> Result<Header<'_>> {
+    let invalid = || Error::format("Invalid age header");
+    if !is_age(data) {
+        return Err(Error::format("Not an age file"));
+    }
+
+    let mut cursor = VERSION_LINE.len();
+    let next_line = |cursor: &mut usize| -> Result<&[u8]> {
+        let rest = &data[*cursor..];
+        let end = rest.iter().position(|&b| b == b'\n').ok_or_else(invalid)?;
+        *cursor += end + 1;
+        Ok(&rest[..end])
+    };
+
+    let mut stanzas = Vec::new();
+    loop {
+        let line_start = cursor;
+        let line = std::str::from_utf8(next_line(&mut cursor)?).map_err(|_| invalid())?;
+
+        if let Some(mac) = line.strip_prefix("--- ") {
+            return Ok(Header {
+                stanzas,
+                mac_input: &data[..line_start + 3],
+                mac: STANDARD_NO_PAD.decode(mac).map_err(|_| invalid())?,
+                payload: &data[cursor..],
+            });
+        }
+
+        let mut parts = line.strip_prefix("-> ").ok_or_else(invalid)?.split(' ');
+        let tag = parts
+            .next()
+            .filter(|tag| !tag.is_empty())
+            .ok_or_else(invalid)?;
+        let args = parts.map(str::to_string).collect();
+
+        let mut body = String::new();
+        loop {
+            let line = std::str::from_utf8(next_line(&mut cursor)?).map_err(|_| invalid())?;
+            if line.len() > BODY_COLUMNS {
+                return Err(invalid());
+            }
+            body.push_str(line);
+            if line.len() < BODY_COLUMNS {
+                break;
+            }
+        }
+
+        stanzas.push(Stanza {
+            tag: tag.to_string(),
+            args,
+            body: STANDARD_NO_PAD.decode(body).map_err(|_| invalid())?,
+        });
+    }
+}
+
+pub fn decrypt(data: &[u8], identity: &AgeIdentity) -> Result<Vec<u8>> {
+    let Header {
+        stanzas,
+        mac_input,
+        mac,
+        payload,
+    } = parse_header(data)?;
+    if stanzas.len() > 1 && stanzas.iter().any(|stanza| stanza.tag == "scrypt") {
+        return Err(Error::format("Invalid age header"));
+    }
+
+    let mut file_key = None;
+    for stanza in &stanzas {
+        if let Some(key) = unwrap_file_key(stanza, identity)? {
+            file_key = Some(key);
+            break;
+        }
+    }
+    let file_key = file_key.ok_or_else(|| Error::crypto("No matching age identity"))?;
+
+    header_mac(&file_key, mac_input)
+        .verify_slice(&mac)
+        .map_err(|_| Error::crypto("Invalid age header MAC"))?;
+
+    if payload.len() < 16 {
+        return Err(Error::format("Truncated age payload"));
+    }
+    let (nonce, payload) = payload.split_at(16);
+    let payload_key = hkdf(&file_key, nonce, b"payload");
+    let cipher = ChaCha20Poly1305::new(&payload_key.into());
+
+    let mut output = Vec::with_capacity(payload.len());
+    let chunks: Vec<&[u8]> = payload.chunks(STREAM_CHUNK_SIZE + TAG_SIZE).collect();
+    if chunks.is_empty() {
+        return Err(Error::format("Truncated age payload"));
+    }
+    for (counter, chunk) in chunks.iter().enumerate() {
+        let last = counter + 1 == chunks.len();
+        let nonce = stream_nonce(counter as u64, last);
+        let plaintext = cipher
+            .decrypt(&nonce.into(), *chunk)
+            .map_err(|_| Error::crypto("Corrupted age payload"))?;
+        if last && plaintext.is_empty() && counter > 0 {
+            return Err(Error::crypto("Corrupted age payload"));
+        }
+        output.extend_from_slice(&plaintext);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrypt(work_factor: u8) -> AgeRecipient {
+        AgeRecipient::Scrypt {
+            passphrase: "hunter2".to_string(),
+            work_factor,
+        }
+    }
+
+    /// Sizes on both sides of the 64 KiB STREAM chunk boundary.
+    const SIZES: [usize; 7] = [
+        0,
+        1,
+        STREAM_CHUNK_SIZE - 1,
+        STREAM_CHUNK_SIZE,
+        STREAM_CHUNK_SIZE + 1,
+        2 * STREAM_CHUNK_SIZE,
+        2 * STREAM_CHUNK_SIZE + 1,
+    ];
+
+    fn content(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn scrypt_round_trip() {
+        for len in SIZES {
+            let sealed = encrypt(&content(len), &[scrypt(10)]).unwrap();
+            let opened = decrypt(&sealed, &AgeIdentity::Passphrase("hunter2")).unwrap();
+            assert_eq!(opened, content(len), "{} bytes", len);
+            assert!(decrypt(&sealed, &AgeIdentity::Passphrase("hunter3")).is_err());
+        }
+    }
+
+    #[test]
+    fn x25519_round_trip() {
+        let secret = StaticSecret::from([3; 32]);
+        let other = StaticSecret::from([4; 32]);
+        let recipients = [
+            AgeRecipient::X25519(PublicKey::from(&other)),
+            AgeRecipient::X25519(PublicKey::from(&secret)),
+        ];
+        for len in SIZES {
+            let sealed = encrypt(&content(len), &recipients).unwrap();
+            // Every recipient opens it, whichever stanza is theirs
+            for identity in [&secret, &other] {
+                let opened = decrypt(&sealed, &AgeIdentity::X25519(identity.clone())).unwrap();
+                assert_eq!(opened, content(len), "{} bytes", len);
+            }
+            let stranger = AgeIdentity::X25519(StaticSecret::from([5; 32]));
+            assert!(decrypt(&sealed, &stranger).is_err());
+        }
+    }
+
+    #[test]
+    fn truncated_payload_is_rejected() {
+        let secret = StaticSecret::from([3; 32]);
+        let identity = AgeIdentity::X25519(secret.clone());
+        let sealed = encrypt(
+            &content(2 * STREAM_CHUNK_SIZE + 1),
+            &[AgeRecipient::X25519(PublicKey::from(&secret))],
+        )
+        .unwrap();
+        // Dropping the last chunk leaves a chunk that was not sealed as the last one
+        let cut = sealed.len() - (1 + TAG_SIZE);
+        assert!(decrypt(&sealed[..cut], &identity).is_err());
+    }
+
+    #[test]
+    fn work_factor_above_the_cap_is_rejected() {
+        assert!(encrypt(b"secret", &[scrypt(MAX_WORK_FACTOR + 1)]).is_err());
+
+        // Raise the factor in the header; the cap is checked before scrypt runs
+        let mut sealed = encrypt(b"secret", &[scrypt(10)]).unwrap();
+        let at = sealed.windows(4).position(|w| w == b" 10\n").unwrap();
+        sealed[at + 1..at + 3].copy_from_slice(b"19");
+        let err = decrypt(&sealed, &AgeIdentity::Passphrase("hunter2"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("too large"));
+    }
+}
@@ -0,0 +1,100 @@
+//! The AES-GCM and PBKDF2 primitives behind `Encryptor` and key derivation. `RustCrypto`
+//! runs everywhere; platforms with native implementations, such as SubtleCrypto in the
+//! browser, plug in their own. All backends produce byte-identical output, so either can
+//! open what the other sealed.
+
+use crate::error::{Error, Result};
+use aes_gcm::{
+    Aes256Gcm,
+    aead::{Aead, KeyInit},
+};
+use futures::FutureExt;
+use generic_array::GenericArray;
+use hmac::Hmac;
+use sha2::Sha256;
+
+// The futures are not `Send`: those of SubtleCrypto cannot be
+#[allow(async_fn_in_trait)]
+pub trait CryptoBackend {
+    /// AES-256-GCM with a 96-bit nonce; the 16-byte tag is appended to the ciphertext.
+    async fn aes_gcm_seal(
+        &self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>>;
+
+    async fn aes_gcm_open(
+        &self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>>;
+
+    /// PBKDF2-HMAC-SHA256 with a 256-bit output.
+    async fn pbkdf2_sha256(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+    ) -> Result<[u8; 32]>;
+
+    /// Whether AES-GCM runs on the memory the plaintext is in, so `Encryptor` can seal
+    /// and open whole ciphertexts in place rather than segment by segment.
+    fn works_in_place(&self) -> bool {
+        false
+    }
+}
+
+/// The pure-Rust implementation. Its futures are always ready, so synchronous callers
+/// drive them with `run_sync`.
+pub struct RustCrypto;
+
+/// Resolves a future that only awaits `RustCrypto`.
+pub fn run_sync<T>(future: impl Future<Output = T>) -> T {
+    future
+        .now_or_never()
+        .expect("RustCrypto operations complete immediately")
+}
+
+impl CryptoBackend for RustCrypto {
+    async fn aes_gcm_seal(
+        &self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        let nonce = GenericArray::from_slice(nonce);
+        Aes256Gcm::new(&(*key).into())
+            .encrypt(nonce.as_0_14(), plaintext)
+            .map_err(|_| Error::crypto("Encryption failed"))
+    }
+
+    async fn aes_gcm_open(
+        &self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>> {
+        let nonce = GenericArray::from_slice(nonce);
+        Aes256Gcm::new(&(*key).into())
+            .decrypt(nonce.as_0_14(), ciphertext)
+            .map_err(|_| Error::crypto("Decryption failed"))
+    }
+
+    async fn pbkdf2_sha256(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+    ) -> Result<[u8; 32]> {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(password, salt, iterations, &mut key)
+            .map_err(|e| Error::Backend(e.to_string()))?;
+        Ok(key)
+    }
+
+    fn works_in_place(&self) -> bool {
+        true
+    }
+}
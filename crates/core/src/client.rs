@@ -0,0 +1,485 @@
+//! The tsbin API, over whichever `Transport` the platform provides.
+
+use crate::{
+    error::{Error, Result},
+    transport::{Body, Part, Request, Response, Transport},
+    types::{
+        ApiResponse, ChunkUploadResponse, CreatedTrash, EncryptedText, Trash, TrashAccess,
+        TrashMeta,
+    },
+};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+pub struct TsbinClient<T> {
+    base_url: String,
+    auth_token: String,
+    transport: T,
+}
+
+impl<T: Transport> TsbinClient<T> {
+    pub fn new(base_url: String, auth_token: String, transport: T) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth_token,
+            transport,
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Sends a request to `endpoint`, turning error statuses into `Error::Api` with the
+    /// message of the error body.
+    async fn make_request(
+        &self,
+        endpoint: &str,
+        method: &'static str,
+        body: Body,
+    ) -> Result<Response> {
+        let request = Request {
+            method,
+            url: format!("{}{}", self.base_url, endpoint),
+            headers: vec![("Authorization", format!("Bearer {}", self.auth_token))],
+            body,
+        };
+        let response = self.transport.send(request).await?;
+        if !(200..300).contains(&response.status) {
+            let message = serde_json::from_slice::<ApiResponse<IgnoredAny>>(&response.body)
+                .ok()
+                .and_then(|body| body.message);
+            return Err(Error::api(response.status, message));
+        }
+        Ok(response)
+    }
+
+    /// Sends a request whose successful response carries data in its envelope.
+    async fn request_data<D: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        method: &'static str,
+        body: Body,
+    ) -> Result<D> {
+        let response = self.make_request(endpoint, method, body).await?;
+        serde_json::from_slice::<ApiResponse<D>>(&response.body)?
+            .into_data(response.status)?
+            .ok_or_else(|| Error::format("Response carries no data"))
+    }
+
+    /// Sends a request whose envelope only reports success.
+    async fn request_empty(&self, endpoint: &str, method: &'static str, body: Body) -> Result<()> {
+        let response = self.make_request(endpoint, method, body).await?;
+        serde_json::from_slice::<ApiResponse<IgnoredAny>>(&response.body)?
+            .into_data(response.status)?;
+        Ok(())
+    }
+
+    pub async fn upload_text(
+        &self,
+        encrypted_text: &str,
+        metadata: &serde_json::Value,
+        access: &TrashAccess,
+    ) -> Result<String> {
+        let body = json!({
+            "enc_trash_text": encrypted_text,
+            "encryption_metadata": metadata,
+            "text_length": encrypted_text.len(),
+            "passcode_hash": access.passcode_hash,
+            "slot_passcode_hashes": access.slot_passcode_hashes,
+            "owner_token": access.owner_token,
+        });
+        let created: CreatedTrash = self
+            .request_data("/trash/text", "POST", Body::Json(body))
+            .await?;
+        Ok(created.trash_id)
+    }
+
+    pub async fn get_file_trash_meta(&self, trash_id: &str) -> Result<TrashMeta> {
+        let endpoint = format!("/trash/file?file_id={}", trash_id);
+        self.request_data(&endpoint, "GET", Body::Empty).await
+    }
+
+    pub async fn get_text_obj(&self, trash_id: &str, passcode_hash: &str) -> Result<EncryptedText> {
+        let endpoint = format!(
+            "/trash/text?id={}&passcode={}",
+            trash_id,
+            urlencode(passcode_hash)
+        );
+        self.request_data(&endpoint, "GET", Body::Empty).await
+    }
+
+    pub async fn get_trash(&self, trash_id: &str) -> Result<Trash> {
+        let endpoint = format!("/trash/{}", trash_id);
+        self.request_data(&endpoint, "GET", Body::Empty).await
+    }
+
+    /// Replaces the key slots of a trash, with its owner token or, for trashes created
+    /// before owner tokens, its passcode hash. `slot_passcode_hashes` only needs the slots
+    /// that are new or changed; the server keeps the hashes of the others.
+    pub async fn update_encryption_metadata(
+        &self,
+        trash_id: &str,
+        owner_token: Option<&str>,
+        passcode_hash: &str,
+        metadata: &serde_json::Value,
+        slot_passcode_hashes: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let body = json!({
+            "owner_token": owner_token,
+            "passcode": passcode_hash,
+            "encryption_metadata": metadata,
+            "slot_passcode_hashes": slot_passcode_hashes,
+        });
+        let endpoint = format!("/trash/{}", trash_id);
+        self.request_empty(&endpoint, "PATCH", Body::Json(body))
+            .await
+    }
+
+    /// Deletes a trash with its owner token. Trashes created before owner tokens only
+    /// take their passcode hash, and cannot be deleted when they have no passcode.
+    pub async fn delete_trash(
+        &self,
+        trash_id: &str,
+        owner_token: Option<&str>,
+        passcode_hash: &str,
+    ) -> Result<()> {
+        let mut endpoint = format!("/trash/{}?passcode={}", trash_id, urlencode(passcode_hash));
+        if let Some(owner_token) = owner_token {
+            endpoint.push_str(&format!("&owner_token={}", urlencode(owner_token)));
+        }
+        self.request_empty(&endpoint, "DELETE", Body::Empty).await
+    }
+
+    pub async fn upload_chunk(
+        &self,
+        chunk_data: &[u8],
+        chunk_index: u32,
+    ) -> Result<ChunkUploadResponse> {
+        let body = Body::Multipart(vec![
+            Part {
+                name: "chunk",
+                file_name: Some(format!("chunk_{}", chunk_index)),
+                data: chunk_data.to_vec(),
+            },
+            Part {
+                name: "chunk_index",
+                file_name: None,
+                data: chunk_index.to_string().into_bytes(),
+            },
+        ]);
+        // The one response that is not wrapped: its fields sit next to `success`
+        let response = self.make_request("/trash/chunk", "POST", body).await?;
+        serde_json::from_slice::<ApiResponse<IgnoredAny>>(&response.body)?
+            .into_data(response.status)?;
+        Ok(serde_json::from_slice(&response.body)?)
+    }
+
+    pub async fn create_file_trash(
+        &self,
+        file_ids: Vec<String>,
+        message_ids: Vec<u32>,
+        metadata: &serde_json::Value,
+        access: &TrashAccess,
+    ) -> Result<String> {
+        let body = json!({
+            "message_ids": message_ids,
+            "file_ids": file_ids,
+            "encryption_metadata": metadata,
+            "passcode_hash": access.passcode_hash,
+            "slot_passcode_hashes": access.slot_passcode_hashes,
+            "owner_token": access.owner_token,
+        });
+        let created: CreatedTrash = self
+            .request_data("/trash/file", "POST", Body::Json(body))
+            .await?;
+        Ok(created.trash_id)
+    }
+
+    pub async fn download_chunk(&self, trash_id: &str, file_id: &str) -> Result<Vec<u8>> {
+        let endpoint = format!("/trash/chunk?trash_id={}&file_id={}", trash_id, file_id);
+        let response = self.make_request(&endpoint, "GET", Body::Empty).await?;
+        Ok(response.body)
+    }
+}
+
+/// Passcode hashes are base64, whose `+` and `/` would not survive a query string.
+fn urlencode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Fault, fixtures::client};
+    use futures::executor::block_on;
+
+    fn access(passcode_hash: &str) -> TrashAccess {
+        TrashAccess {
+            passcode_hash: passcode_hash.to_string(),
+            slot_passcode_hashes: BTreeMap::new(),
+            owner_token: None,
+        }
+    }
+
+    #[test]
+    fn text_round_trip_checks_the_passcode() {
+        let client = client();
+        let metadata = json!({ "encryption_type": "aes-gcm" });
+        let slug = block_on(client.upload_text("c2VhbGVk", &metadata, &access("a+b/c="))).unwrap();
+
+        let trash = block_on(client.get_trash(&slug)).unwrap();
+        assert_eq!(trash.trash_type, "TEXT");
+        assert!(trash.encrypted);
+        let object_id = trash.object_id.unwrap();
+
+        let text = block_on(client.get_text_obj(&object_id, "a+b/c=")).unwrap();
+        assert_eq!(text.enc_trash_text, "c2VhbGVk");
+        assert_eq!(text.encryption_type.as_deref(), Some("aes-gcm"));
+        assert_eq!(text.encryption_metadata, Some(metadata));
+
+        let error = block_on(client.get_text_obj(&object_id, "wrong")).unwrap_err();
+        assert!(matches!(error, Error::Api { status: 403, .. }));
+        assert_eq!(error.message(), "HTTP error: 403 (Passcode is incorrect)");
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let client = client();
+        let first = block_on(client.upload_chunk(b"first", 0)).unwrap();
+        let second = block_on(client.upload_chunk(b"second", 1)).unwrap();
+        assert_eq!(second.chunk_index, 1);
+
+        let metadata = json!({ "filename": "a.txt", "original_size": 11 });
+        let slug = block_on(client.create_file_trash(
+            vec![first.file_id.clone(), second.file_id.clone()],
+            vec![first.message_id, second.message_id],
+            &metadata,
+            &access("0000"),
+        ))
+        .unwrap();
+
+        let trash = block_on(client.get_trash(&slug)).unwrap();
+        let object_id = trash.object_id.unwrap();
+        let meta = block_on(client.get_file_trash_meta(&object_id)).unwrap();
+        assert_eq!(meta.file_name, "a.txt");
+        assert_eq!(meta.mime_type, "application/octet-stream");
+        assert_eq!(
+            meta.file_ids.unwrap(),
+            vec![first.file_id.clone(), second.file_id]
+        );
+        assert_eq!(
+            block_on(client.download_chunk(&object_id, &first.file_id)).unwrap(),
+            b"first"
+        );
+    }
+
+    #[test]
+    fn faults_surface_as_errors() {
+        let client = client();
+        client.transport().inject(Fault::DropChunk(0));
+        client
+            .transport()
+            .inject(Fault::ServerError("/trash/chunk"));
+        assert!(matches!(
+            block_on(client.upload_chunk(b"data", 0)),
+            Err(Error::Transport(message)) if message == "Connection reset"
+        ));
+        assert!(matches!(
+            block_on(client.upload_chunk(b"data", 0)),
+            Err(Error::Api { status: 500, message }) if message == "HTTP error: 500 (Internal server error)"
+        ));
+        assert!(block_on(client.upload_chunk(b"data", 0)).is_ok());
+        assert_eq!(client.transport().chunk_count(), 1);
+    }
+
+    #[test]
+    fn delete_requires_the_owner_token() {
+        let client = client();
+        let access = TrashAccess {
+            owner_token: Some("a+b/c=".to_string()),
+            ..access("hash")
+        };
+        let slug = block_on(client.upload_text("", &json!({}), &access)).unwrap();
+
+        // The passcode no longer does once there is an owner token
+        assert!(block_on(client.delete_trash(&slug, None, "hash")).is_err());
+        assert!(block_on(client.delete_trash(&slug, Some("wrong"), "hash")).is_err());
+        block_on(client.delete_trash(&slug, Some("a+b/c="), "0000")).unwrap();
+        let error = block_on(client.get_trash(&slug)).unwrap_err();
+        assert_eq!(
+            error.message(),
+            "HTTP error: 500 (DB: Failed to find trash by ID - Trash not found)"
+        );
+    }
+
+    #[test]
+    fn trashes_without_an_owner_token_need_their_passcode() {
+        let client = client();
+        let slug = block_on(client.upload_text("", &json!({}), &access("hash"))).unwrap();
+        assert!(block_on(client.delete_trash(&slug, None, "wrong")).is_err());
+        block_on(client.delete_trash(&slug, None, "hash")).unwrap();
+
+        // Public ones have nothing to check, so nobody can delete them
+        let slug = block_on(client.upload_text("", &json!({}), &access("0000"))).unwrap();
+        let error = block_on(client.delete_trash(&slug, None, "0000")).unwrap_err();
+        assert!(matches!(error, Error::Api { status: 403, .. }));
+    }
+
+    #[test]
+    fn passcode_hashes_are_never_served() {
+        let client = client();
+        let metadata = json!({
+            "passcode_hash": "main",
+            "key_slots": [{ "id": "a", "passcode_hash": "main" }, { "id": "b" }],
+        });
+        let access = TrashAccess {
+            passcode_hash: "main".to_string(),
+            slot_passcode_hashes: BTreeMap::from([
+                ("a".to_string(), "main".to_string()),
+                ("b".to_string(), "extra".to_string()),
+            ]),
+            owner_token: None,
+        };
+        let slug = block_on(client.upload_text("", &metadata, &access)).unwrap();
+        let object_id = block_on(client.get_trash(&slug))
+            .unwrap()
+            .object_id
+            .unwrap();
+
+        // Every slot passcode passes the check, but none of the hashes comes back
+        let text = block_on(client.get_text_obj(&object_id, "extra")).unwrap();
+        assert_eq!(
+            text.encryption_metadata,
+            Some(json!({ "key_slots": [{ "id": "a" }, { "id": "b" }] }))
+        );
+    }
+
+    #[test]
+    fn metadata_update_only_replaces_the_key_slots() {
+        let client = client();
+        let metadata = json!({ "manifest_mac": "mac", "key_slots": [{ "id": "a" }] });
+        let access = TrashAccess {
+            passcode_hash: "main".to_string(),
+            slot_passcode_hashes: BTreeMap::from([("a".to_string(), "main".to_string())]),
+            owner_token: Some("token".to_string()),
+        };
+        let slug = block_on(client.upload_text("", &metadata, &access)).unwrap();
+        let object_id = block_on(client.get_trash(&slug))
+            .unwrap()
+            .object_id
+            .unwrap();
+
+        let update = json!({
+            "key_slots": [{ "id": "b" }, { "id": "a" }],
+            "manifest_mac": "forged",
+        });
+        let hashes = BTreeMap::from([("b".to_string(), "next".to_string())]);
+        block_on(client.update_encryption_metadata(&slug, Some("token"), "0000", &update, &hashes))
+            .unwrap();
+        let stored = client.transport().metadata(&slug).unwrap();
+        assert_eq!(stored["key_slots"], json!([{ "id": "b" }, { "id": "a" }]));
+        assert_eq!(stored["manifest_mac"], "mac");
+        // Both slots still open the trash
+        assert!(block_on(client.get_text_obj(&object_id, "main")).is_ok());
+        assert!(block_on(client.get_text_obj(&object_id, "next")).is_ok());
+
+        // Dropping a slot drops its passcode
+        let update = json!({ "key_slots": [{ "id": "b" }] });
+        block_on(client.update_encryption_metadata(
+            &slug,
+            Some("token"),
+            "0000",
+            &update,
+            &BTreeMap::new(),
+        ))
+        .unwrap();
+        assert!(block_on(client.get_text_obj(&object_id, "main")).is_err());
+
+        let error = block_on(client.update_encryption_metadata(
+            &slug,
+            Some("token"),
+            "0000",
+            &json!({}),
+            &BTreeMap::new(),
+        ))
+        .unwrap_err();
+        assert!(matches!(error, Error::Api { status: 400, .. }));
+    }
+
+    #[test]
+    fn metadata_update_requires_the_owner_token() {
+        let client = client();
+        let access = TrashAccess {
+            owner_token: Some("token".to_string()),
+            ..access("0000")
+        };
+        let slug = block_on(client.upload_text("", &json!({}), &access)).unwrap();
+        let update = json!({ "key_slots": [{ "id": "a" }] });
+        let hashes = BTreeMap::from([("a".to_string(), "mine".to_string())]);
+
+        // A public trash passes the passcode check, which must not let anyone take it over
+        for owner_token in [None, Some("wrong")] {
+            let error = block_on(client.update_encryption_metadata(
+                &slug,
+                owner_token,
+                "0000",
+                &update,
+                &hashes,
+            ))
+            .unwrap_err();
+            assert!(matches!(error, Error::Api { status: 403, .. }));
+        }
+        assert!(client.transport().metadata(&slug).unwrap()["key_slots"].is_null());
+    }
+
+    #[test]
+    fn error_envelopes_are_typed() {
+        let envelope: ApiResponse<Trash> = serde_json::from_str(
+            r#"{"success":false,"message":"Trash not found","data":null,"path":"/v1/trash/x","timestamp":"2025-01-01T00:00:00.000Z"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            envelope.into_data(200).unwrap_err(),
+            Error::Api {
+                status: 200,
+                message: "HTTP error: 200 (Trash not found)".to_string()
+            }
+        );
+
+        let envelope: ApiResponse<CreatedTrash> =
+            serde_json::from_str(r#"{"success":true}"#).unwrap();
+        assert!(envelope.into_data(201).unwrap().is_none());
+    }
+
+    #[test]
+    fn find_trash_by_id_body_deserializes() {
+        let envelope: ApiResponse<Trash> = serde_json::from_str(
+            r#"{"success":true,"data":{"badReport":0,"createdAt":"2025-03-02T10:41:07.512Z","encrypted":true,"expireAt":"2025-03-09T10:41:07.511Z","id":"cm7rw1x0k0003qs01h8m2l6vd","slug":"k3Jd9aQ","type":"FILE","objectId":"cm7rw1x0k0004qs01b1zq7c2e"},"message":"Trash retrieved successfully"}"#,
+        )
+        .unwrap();
+        let trash = envelope.into_data(200).unwrap().unwrap();
+        assert_eq!(trash.bad_report, 0);
+        assert_eq!(trash.trash_type, "FILE");
+        assert_eq!(
+            trash.object_id.as_deref(),
+            Some("cm7rw1x0k0004qs01b1zq7c2e")
+        );
+        assert_eq!(trash.expire_at.as_deref(), Some("2025-03-09T10:41:07.511Z"));
+    }
+
+    #[test]
+    fn urlencode_escapes_base64() {
+        assert_eq!(urlencode("a+b/c="), "a%2Bb%2Fc%3D");
+        assert_eq!(urlencode("A-z_0.~"), "A-z_0.~");
+    }
+}
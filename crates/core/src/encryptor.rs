@@ -0,0 +1,565 @@
+use crate::{
+    backend::CryptoBackend,
+    error::{Error, Result},
+    rawkey, utils,
+};
+use aes_gcm::{
+    Aes256Gcm,
+    aead::{Aead, AeadInPlace, KeyInit},
+};
+
+use chacha20poly1305::XChaCha20Poly1305;
+use generic_array::GenericArray;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::{Zeroize, Zeroizing};
+
+pub struct Encryptor {
+    key: Zeroizing<[u8; 32]>,
+    cipher: Cipher,
+    require_commitment: bool,
+    chunk_index: u32,
+}
+
+/// The AEAD sealing every segment, recorded as the trash `encryption_type`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Cipher {
+    /// Counter nonces from the chunk and segment index
+    #[default]
+    Aes256Gcm,
+    /// Random 192-bit nonces, so nonce reuse is not a concern
+    XChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub fn parse(encryption_type: &str) -> Result<Self> {
+        match encryption_type {
+            "aes256gcm" => Ok(Cipher::Aes256Gcm),
+            "xchacha20poly1305" => Ok(Cipher::XChaCha20Poly1305),
+            other => Err(Error::invalid(format!(
+                "Unsupported encryption type: {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "aes256gcm",
+            Cipher::XChaCha20Poly1305 => "xchacha20poly1305",
+        }
+    }
+
+    fn nonce_len(self) -> usize {
+        match self {
+            Cipher::Aes256Gcm => 12,
+            Cipher::XChaCha20Poly1305 => 24,
+        }
+    }
+}
+
+const CHUNK_SIZE: usize = 5 * 1024 * 1024; // 5MB
+
+/// Starts every ciphertext that carries a key commitment; nonces of the legacy format
+/// begin with a zero segment index, so the two cannot be confused.
+pub(crate) const COMMITMENT_MAGIC: &[u8; 4] = b"TSB\x01";
+const COMMITMENT_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+
+impl Encryptor {
+    pub fn from_key(key_bytes: [u8; 32]) -> Self {
+        Self {
+            key: Zeroizing::new(key_bytes),
+            cipher: Cipher::default(),
+            require_commitment: false,
+            chunk_index: 0,
+        }
+    }
+
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    pub fn key(&self) -> &[u8] {
+        &self.key[..]
+    }
+
+    /// Rejects ciphertexts without a key commitment instead of reading them as legacy data,
+    /// for trashes whose metadata says they were written with one.
+    pub fn require_commitment(mut self) -> Self {
+        self.require_commitment = true;
+        self
+    }
+
+    /// Whether this encryptor only accepts ciphertexts with a key commitment, which means
+    /// the trash is not of the legacy format.
+    pub fn requires_commitment(&self) -> bool {
+        self.require_commitment
+    }
+
+    /// The encryptor for chunk `index` of a file trash. Every chunk is sealed under the same
+    /// data key, so its index goes into the AES-GCM nonces to keep them from repeating.
+    pub fn for_chunk(&self, index: u32) -> Self {
+        Self {
+            key: self.key.clone(),
+            chunk_index: index,
+            ..*self
+        }
+    }
+
+    /// AES-GCM is not key-committing: a crafted ciphertext can open under many keys. Every
+    /// ciphertext therefore starts with an HMAC of a fixed label under a subkey derived from
+    /// the key, which is checked before any segment is decrypted.
+    fn commitment(&self) -> Hmac<Sha256> {
+        let mut subkey = [0u8; 32];
+        Hkdf::<Sha256>::new(None, &self.key[..])
+            .expand(b"tsbin/key-commitment/v1", &mut subkey)
+            .expect("valid hkdf length");
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(&subkey).expect("HMAC accepts any key length");
+        mac.update(b"tsbin key commitment");
+        mac
+    }
+
+    /// The AES-GCM nonce for segment `index`: the chunk index in the high four bytes and
+    /// the segment index in the low eight.
+    fn counter_nonce(&self, index: u64) -> [u8; 12] {
+        let mut nonce_bytes = [0u8; 12];
+        nonce_bytes[..4].copy_from_slice(&self.chunk_index.to_be_bytes());
+        nonce_bytes[4..].copy_from_slice(&index.to_be_bytes());
+        nonce_bytes
+    }
+
+    /// The nonce for segment `index`: a counter for AES-GCM, random for XChaCha20.
+    fn segment_nonce(&self, index: u64) -> Vec<u8> {
+        match self.cipher {
+            Cipher::Aes256Gcm => self.counter_nonce(index).to_vec(),
+            Cipher::XChaCha20Poly1305 => utils::random_bytes::<24>().to_vec(),
+        }
+    }
+
+    /// Rejects an AES-GCM segment of a committed ciphertext whose nonce is not the counter
+    /// for its place, which a moved, repeated or reordered segment would carry. Legacy
+    /// ciphertexts predate the counter layout and are not checked.
+    fn check_nonce(&self, committed: bool, index: u64, nonce_bytes: &[u8]) -> Result<()> {
+        if committed && self.cipher == Cipher::Aes256Gcm && nonce_bytes != self.counter_nonce(index)
+        {
+            return Err(Error::crypto("Segment nonce out of sequence"));
+        }
+        Ok(())
+    }
+
+    /// Seals one segment, returning the nonce it was sealed under and the ciphertext.
+    async fn seal_segment(
+        &self,
+        backend: &impl CryptoBackend,
+        index: u64,
+        segment: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let nonce_bytes = self.segment_nonce(index);
+        let ciphertext = match self.cipher {
+            Cipher::Aes256Gcm => {
+                let nonce = nonce_bytes[..].try_into().expect("12-byte nonce");
+                backend.aes_gcm_seal(&self.key, nonce, segment).await?
+            }
+            Cipher::XChaCha20Poly1305 => {
+                let nonce = GenericArray::from_slice(&nonce_bytes);
+                XChaCha20Poly1305::new(&(*self.key).into())
+                    .encrypt(nonce.as_0_14(), segment)
+                    .map_err(|_| Error::crypto("Encryption failed"))?
+            }
+        };
+        Ok((nonce_bytes, ciphertext))
+    }
+
+    async fn open_segment(
+        &self,
+        backend: &impl CryptoBackend,
+        nonce_bytes: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>> {
+        match self.cipher {
+            Cipher::Aes256Gcm => {
+                let nonce_bytes = nonce_bytes.try_into().expect("12-byte nonce");
+                backend
+                    .aes_gcm_open(&self.key, nonce_bytes, ciphertext)
+                    .await
+            }
+            Cipher::XChaCha20Poly1305 => {
+                let nonce = GenericArray::from_slice(nonce_bytes);
+                XChaCha20Poly1305::new(&(*self.key).into())
+                    .decrypt(nonce.as_0_14(), ciphertext)
+                    .map_err(|_| Error::crypto("Decryption failed"))
+            }
+        }
+    }
+
+    /// Encrypts `buffer` where it lies and returns the detached tag.
+    fn seal_in_place(&self, nonce_bytes: &[u8], buffer: &mut [u8]) -> [u8; TAG_LEN] {
+        let tag = match self.cipher {
+            Cipher::Aes256Gcm => {
+                let nonce = GenericArray::from_slice(nonce_bytes);
+                Aes256Gcm::new(&(*self.key).into()).encrypt_in_place_detached(
+                    nonce.as_0_14(),
+                    b"",
+                    buffer,
+                )
+            }
+            Cipher::XChaCha20Poly1305 => {
+                let nonce = GenericArray::from_slice(nonce_bytes);
+                XChaCha20Poly1305::new(&(*self.key).into()).encrypt_in_place_detached(
+                    nonce.as_0_14(),
+                    b"",
+                    buffer,
+                )
+            }
+        };
+        tag.expect("encryption failure").into()
+    }
+
+    fn open_in_place(&self, nonce_bytes: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<()> {
+        let tag = GenericArray::from_slice(tag).as_0_14();
+        let opened = match self.cipher {
+            Cipher::Aes256Gcm => {
+                let nonce = GenericArray::from_slice(nonce_bytes);
+                Aes256Gcm::new(&(*self.key).into()).decrypt_in_place_detached(
+                    nonce.as_0_14(),
+                    b"",
+                    buffer,
+                    tag,
+                )
+            }
+            Cipher::XChaCha20Poly1305 => {
+                let nonce = GenericArray::from_slice(nonce_bytes);
+                XChaCha20Poly1305::new(&(*self.key).into()).decrypt_in_place_detached(
+                    nonce.as_0_14(),
+                    b"",
+                    buffer,
+                    tag,
+                )
+            }
+        };
+        opened.map_err(|_| Error::crypto("Decryption failed"))
+    }
+
+    /// Checks the key commitment and returns where the first segment starts.
+    fn payload_start(&self, data: &[u8]) -> Result<usize> {
+        if let Some(header) = data.strip_prefix(COMMITMENT_MAGIC) {
+            let commitment = header
+                .get(..COMMITMENT_LEN)
+                .ok_or_else(|| Error::format("Ciphertext truncated"))?;
+            self.commitment()
+                .verify_slice(commitment)
+                .map_err(|_| Error::crypto("Wrong key for this ciphertext"))?;
+            Ok(COMMITMENT_MAGIC.len() + COMMITMENT_LEN)
+        } else if self.require_commitment {
+            Err(Error::crypto("Ciphertext is missing its key commitment"))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// The size of the ciphertext for `plaintext_len` bytes.
+    fn sealed_len(&self, plaintext_len: usize) -> usize {
+        let segments = plaintext_len.div_ceil(CHUNK_SIZE);
+        COMMITMENT_MAGIC.len()
+            + COMMITMENT_LEN
+            + plaintext_len
+            + segments * (self.cipher.nonce_len() + 4 + TAG_LEN)
+    }
+
+    /// Encrypts `plaintext_len` bytes into a single buffer of the final size. `fill` writes
+    /// the plaintext at the given offset straight into its place in the output, where it is
+    /// then sealed, so no segment is ever copied.
+    pub fn encrypt_in_place(
+        &self,
+        plaintext_len: usize,
+        mut fill: impl FnMut(usize, &mut [u8]),
+    ) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.sealed_len(plaintext_len));
+        output.extend_from_slice(COMMITMENT_MAGIC);
+        output.extend_from_slice(&self.commitment().finalize().into_bytes());
+
+        let mut offset = 0;
+        for chunk_index in 0u64.. {
+            if offset >= plaintext_len {
+                break;
+            }
+            let len = CHUNK_SIZE.min(plaintext_len - offset);
+            let nonce_bytes = self.segment_nonce(chunk_index);
+            output.extend_from_slice(&nonce_bytes);
+            output.extend_from_slice(&((len + TAG_LEN) as u32).to_be_bytes());
+
+            let start = output.len();
+            output.resize(start + len, 0);
+            fill(offset, &mut output[start..]);
+            let tag = self.seal_in_place(&nonce_bytes, &mut output[start..]);
+            output.extend_from_slice(&tag);
+            offset += len;
+        }
+        output
+    }
+
+    /// Decrypts every segment where it lies and moves the plaintext to the front of `data`,
+    /// which is then truncated and returned. Nothing else is allocated.
+    pub fn decrypt_in_place(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        // Wiped on the error paths, which may leave some segments decrypted
+        let mut data = Zeroizing::new(data);
+        let nonce_len = self.cipher.nonce_len();
+        let mut cursor = self.payload_start(&data)?;
+        let committed = cursor > 0;
+        let mut written = 0;
+
+        for index in 0u64.. {
+            if cursor >= data.len() {
+                break;
+            }
+            let (nonce_range, chunk_len) = segment_header(&data, cursor, nonce_len)?;
+            let nonce_bytes = data[nonce_range].to_vec();
+            self.check_nonce(committed, index, &nonce_bytes)?;
+            cursor += nonce_len + 4;
+            if chunk_len < TAG_LEN {
+                return Err(Error::crypto("Decryption failed"));
+            }
+
+            let (ciphertext, tag) =
+                data[cursor..cursor + chunk_len].split_at_mut(chunk_len - TAG_LEN);
+            self.open_in_place(&nonce_bytes, ciphertext, tag)?;
+            let plaintext_len = ciphertext.len();
+            data.copy_within(cursor..cursor + plaintext_len, written);
+            written += plaintext_len;
+            cursor += chunk_len;
+        }
+
+        data[written..].zeroize();
+        data.truncate(written);
+        Ok(std::mem::take(&mut *data))
+    }
+
+    /// Seals segment by segment through `backend`, for backends that cannot work in place.
+    pub async fn encrypt_segments(
+        &self,
+        backend: &impl CryptoBackend,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(self.sealed_len(data.len()));
+        output.extend_from_slice(COMMITMENT_MAGIC);
+        output.extend_from_slice(&self.commitment().finalize().into_bytes());
+
+        for (chunk_index, chunk) in (0u64..).zip(data.chunks(CHUNK_SIZE)) {
+            let (nonce_bytes, ciphertext) = self.seal_segment(backend, chunk_index, chunk).await?;
+
+            output.extend_from_slice(&nonce_bytes);
+            output.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+            output.extend_from_slice(&ciphertext);
+        }
+        Ok(output)
+    }
+
+    /// Opens segment by segment through `backend`, the counterpart of `encrypt_segments`.
+    pub async fn decrypt_segments(
+        &self,
+        backend: &impl CryptoBackend,
+        data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let nonce_len = self.cipher.nonce_len();
+        let mut cursor = self.payload_start(data)?;
+        let committed = cursor > 0;
+        // Sized up front so growing the buffer never leaves plaintext copies behind, and
+        // wiped if a later segment fails to open
+        let mut output = Zeroizing::new(Vec::with_capacity(data.len()));
+
+        for index in 0u64.. {
+            if cursor >= data.len() {
+                break;
+            }
+            let (nonce_range, chunk_len) = segment_header(data, cursor, nonce_len)?;
+            let nonce_bytes = &data[nonce_range];
+            self.check_nonce(committed, index, nonce_bytes)?;
+            cursor += nonce_len + 4;
+
+            let ciphertext = &data[cursor..cursor + chunk_len];
+            cursor += chunk_len;
+
+            let plaintext =
+                Zeroizing::new(self.open_segment(backend, nonce_bytes, ciphertext).await?);
+            output.extend_from_slice(&plaintext);
+        }
+        Ok(std::mem::take(&mut *output))
+    }
+
+    /// Derives the key from the passphrase, and keyfile if any, under the legacy salt.
+    pub fn new(passphrase: &str, keyfile: Option<&[u8]>) -> Self {
+        let keyfile_hash = keyfile.map(|keyfile| Zeroizing::new(utils::hash_keyfile(keyfile)));
+        let key_bytes = Zeroizing::new(utils::derive_key(passphrase, keyfile_hash.as_deref()));
+        Self::from_key(*key_bytes)
+    }
+
+    /// Uses 32 raw key bytes as they are, skipping passphrase derivation.
+    pub fn from_raw_key(key: &[u8]) -> Result<Encryptor> {
+        rawkey::from_bytes(key).map(Self::from_key)
+    }
+
+    /// Imports a key exported by `export_key` or `export_jwk`.
+    pub fn import_key(key: &str) -> Result<Encryptor> {
+        rawkey::parse_key(key).map(Self::from_key)
+    }
+
+    /// The key in use, base64 encoded, so it can be cached instead of re-derived.
+    pub fn export_key(&self) -> String {
+        rawkey::to_base64(&self.key[..])
+    }
+
+    /// The key in use as a WebCrypto compatible AES-GCM JWK.
+    pub fn export_jwk(&self) -> String {
+        rawkey::to_jwk(&self.key[..])
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+        self.encrypt_in_place(data.len(), |offset, dst| {
+            dst.copy_from_slice(&data[offset..offset + dst.len()])
+        })
+    }
+
+    pub fn decrypt(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        self.decrypt_in_place(data)
+    }
+
+    /// Encrypts `plaintext_len` bytes written by `fill` through `backend`: in place where it
+    /// allows, otherwise from one plaintext copy that is wiped afterwards.
+    pub async fn encrypt_with(
+        &self,
+        backend: &impl CryptoBackend,
+        plaintext_len: usize,
+        mut fill: impl FnMut(usize, &mut [u8]),
+    ) -> Result<Vec<u8>> {
+        if backend.works_in_place() {
+            return Ok(self.encrypt_in_place(plaintext_len, fill));
+        }
+        let mut data = Zeroizing::new(vec![0; plaintext_len]);
+        fill(0, &mut data);
+        self.encrypt_segments(backend, &data).await
+    }
+
+    /// Decrypts `data` through `backend`, in place where it allows.
+    pub async fn decrypt_with(
+        &self,
+        backend: &impl CryptoBackend,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        match backend.works_in_place() {
+            true => self.decrypt_in_place(data),
+            false => self.decrypt_segments(backend, &data).await,
+        }
+    }
+}
+
+/// Reads the segment header at `cursor`: where its nonce lies and how long its sealed body
+/// is. A segment that does not fit in what is left of `data` is an error, never skipped.
+fn segment_header(
+    data: &[u8],
+    cursor: usize,
+    nonce_len: usize,
+) -> Result<(std::ops::Range<usize>, usize)> {
+    let truncated = || Error::format("Ciphertext truncated");
+    let rest = data.len() - cursor;
+    if rest < nonce_len + 4 {
+        return Err(truncated());
+    }
+    let len_bytes = &data[cursor + nonce_len..cursor + nonce_len + 4];
+    let chunk_len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
+    // Compared against what is left, as `cursor + chunk_len` overflows a 32-bit usize
+    if chunk_len > rest - nonce_len - 4 {
+        return Err(truncated());
+    }
+    Ok((cursor..cursor + nonce_len, chunk_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{RustCrypto, run_sync};
+
+    /// A ciphertext under `key` whose only segment header claims `chunk_len` bytes.
+    fn forged_segment(encryptor: &Encryptor, chunk_len: u32) -> Vec<u8> {
+        let mut data = encryptor.encrypt(&[]);
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&chunk_len.to_be_bytes());
+        data.extend_from_slice(&[0; 32]);
+        data
+    }
+
+    // Used to wrap around on wasm32 and slice out of bounds
+    #[test]
+    fn huge_segment_length_is_rejected() {
+        let encryptor = Encryptor::from_key([7; 32]).require_commitment();
+        let data = forged_segment(&encryptor, u32::MAX);
+        assert!(encryptor.decrypt(data.clone()).is_err());
+        assert!(run_sync(encryptor.decrypt_segments(&RustCrypto, &data)).is_err());
+    }
+
+    #[test]
+    fn truncated_ciphertext_is_rejected() {
+        let encryptor = Encryptor::from_key([7; 32]);
+        let sealed = encryptor.encrypt(b"hello, tsbin");
+        // Cut inside the sealed body, and inside the header of a further segment
+        let mut trailing_header = sealed.clone();
+        trailing_header.extend_from_slice(&[0; 6]);
+        for data in [sealed[..sealed.len() - 1].to_vec(), trailing_header] {
+            assert!(encryptor.decrypt(data.clone()).is_err());
+            assert!(run_sync(encryptor.decrypt_segments(&RustCrypto, &data)).is_err());
+        }
+    }
+
+    #[test]
+    fn segment_shorter_than_its_tag_is_rejected() {
+        let encryptor = Encryptor::from_key([7; 32]).require_commitment();
+        let data = forged_segment(&encryptor, TAG_LEN as u32 - 1);
+        assert!(encryptor.decrypt(data.clone()).is_err());
+        assert!(run_sync(encryptor.decrypt_segments(&RustCrypto, &data)).is_err());
+    }
+
+    #[test]
+    fn chunks_never_share_a_nonce() {
+        let encryptor = Encryptor::from_key([7; 32]).require_commitment();
+        let first = encryptor.for_chunk(0).encrypt(b"same plaintext");
+        let second = encryptor.for_chunk(1).encrypt(b"same plaintext");
+        assert_ne!(first, second);
+
+        let second_chunk = encryptor.for_chunk(1);
+        assert_eq!(
+            second_chunk.decrypt(second.clone()).unwrap(),
+            b"same plaintext"
+        );
+        // A chunk opened in another chunk's place is rejected
+        assert!(encryptor.decrypt(second.clone()).is_err());
+        assert!(run_sync(encryptor.decrypt_segments(&RustCrypto, &second)).is_err());
+        assert!(second_chunk.decrypt(first).is_err());
+    }
+
+    #[test]
+    fn segment_with_a_wrong_nonce_is_rejected() {
+        let encryptor = Encryptor::from_key([7; 32]).require_commitment();
+        let mut sealed = encryptor.encrypt(b"hello, tsbin");
+        // The low byte of the segment index, which the tag does not cover
+        sealed[COMMITMENT_MAGIC.len() + COMMITMENT_LEN + 11] = 1;
+        let error = encryptor.decrypt(sealed.clone()).unwrap_err();
+        assert_eq!(error, Error::crypto("Segment nonce out of sequence"));
+        assert!(run_sync(encryptor.decrypt_segments(&RustCrypto, &sealed)).is_err());
+    }
+
+    #[test]
+    fn round_trip_with_both_ciphers() {
+        for cipher in [Cipher::Aes256Gcm, Cipher::XChaCha20Poly1305] {
+            let encryptor = Encryptor::from_key([7; 32]).with_cipher(cipher);
+            let sealed = encryptor.encrypt(b"hello, tsbin");
+            assert_eq!(
+                run_sync(encryptor.decrypt_segments(&RustCrypto, &sealed)).unwrap(),
+                b"hello, tsbin"
+            );
+            assert_eq!(encryptor.decrypt(sealed).unwrap(), b"hello, tsbin");
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// Why an operation failed. Every variant carries the message shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Input that could not be parsed: base64, JSON, keys, shares or ciphertext framing
+    Format(String),
+    /// Data that does not authenticate under the key at hand, or a key that opens nothing
+    Crypto(String),
+    /// A request that cannot be served as made, such as a missing keyfile or too few shares
+    Invalid(String),
+    /// A failure reported by the platform: the crypto backend itself, or the code the
+    /// bindings call back into
+    Backend(String),
+    /// A request to the tsbin API that got no response
+    Transport(String),
+    /// An error the tsbin API answered with: its status and the message of its body
+    Api { status: u16, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn format(message: impl Into<String>) -> Self {
+        Error::Format(message.into())
+    }
+
+    pub fn crypto(message: impl Into<String>) -> Self {
+        Error::Crypto(message.into())
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Error::Invalid(message.into())
+    }
+
+    pub fn api(status: u16, message: Option<String>) -> Self {
+        let message = match message {
+            Some(message) => format!("HTTP error: {} ({})", status, message),
+            None => format!("HTTP error: {}", status),
+        };
+        Error::Api { status, message }
+    }
+
+    /// The same error, with its message prefixed by what was being done.
+    pub fn context(self, context: impl fmt::Display) -> Self {
+        let prefix = |message: String| format!("{}: {}", context, message);
+        match self {
+            Error::Format(message) => Error::Format(prefix(message)),
+            Error::Crypto(message) => Error::Crypto(prefix(message)),
+            Error::Invalid(message) => Error::Invalid(prefix(message)),
+            Error::Backend(message) => Error::Backend(prefix(message)),
+            Error::Transport(message) => Error::Transport(prefix(message)),
+            Error::Api { status, message } => Error::Api {
+                status,
+                message: prefix(message),
+            },
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Error::Format(message)
+            | Error::Crypto(message)
+            | Error::Invalid(message)
+            | Error::Backend(message)
+            | Error::Transport(message)
+            | Error::Api { message, .. } => message,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<base64::DecodeError> for Error {
+    fn from(error: base64::DecodeError) -> Self {
+        Error::Format(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Format(error.to_string())
+    }
+}
+
+impl From<bech32::Error> for Error {
+    fn from(error: bech32::Error) -> Self {
+        Error::Format(error.to_string())
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<Error> for wasm_bindgen::JsValue {
+    fn from(error: Error) -> Self {
+        wasm_bindgen::JsValue::from_str(error.message())
+    }
+}
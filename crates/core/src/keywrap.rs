@@ -0,0 +1,360 @@
+use crate::{
+    backend::CryptoBackend,
+    error::{Error, Result},
+    utils,
+};
+use base64::{Engine, engine::general_purpose};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// The random data key that encrypts a trash, sealed under one passcode-derived key.
+/// A trash carries one slot per passcode able to open it; binary fields are base64 encoded.
+/// Nothing in a slot tells which passcode sealed it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeySlot {
+    #[serde(default)]
+    pub id: String,
+    /// Whether the passcode has to be combined with a keyfile to open this slot
+    #[serde(default)]
+    pub keyfile: bool,
+    pub salt: String,
+    pub nonce: String,
+    pub wrapped_key: String,
+    /// Binds the slot to the one key that wraps it; see `DerivedKey::commitment`
+    #[serde(default)]
+    pub commitment: String,
+}
+
+pub fn generate_data_key() -> [u8; 32] {
+    utils::random_bytes::<32>()
+}
+
+/// A passcode, and keyfile if any, stretched once under a random salt that every slot it
+/// seals records. It seals and opens any number of those slots without running PBKDF2
+/// again, and is zeroized on drop.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct DerivedKey {
+    #[zeroize(skip)]
+    passcode_hash: String,
+    salt: [u8; 16],
+    key: [u8; 32],
+    keyfile_hash: Option<[u8; 32]>,
+}
+
+/// The portable form of a `DerivedKey`, for handing it between a worker and the page.
+#[derive(Serialize, Deserialize)]
+struct ExportedKey {
+    passcode_hash: String,
+    salt: String,
+    key: String,
+    keyfile_hash: Option<String>,
+}
+
+impl DerivedKey {
+    pub async fn derive(
+        backend: &impl CryptoBackend,
+        passcode: &str,
+        salt: [u8; 16],
+        keyfile_hash: Option<&[u8; 32]>,
+    ) -> Result<Self> {
+        Ok(Self {
+            passcode_hash: utils::hash_passphrase(passcode),
+            salt,
+            key: *utils::derive_key_with(backend, passcode, &salt, keyfile_hash).await?,
+            keyfile_hash: keyfile_hash.copied(),
+        })
+    }
+
+    /// Whether this key was derived from exactly this passcode and keyfile.
+    pub fn matches(&self, passcode: &str, keyfile_hash: Option<&[u8; 32]>) -> bool {
+        self.passcode_hash == utils::hash_passphrase(passcode)
+            && self.keyfile_hash.as_ref() == keyfile_hash
+    }
+
+    /// Whether the slot could have been sealed with this key: it records the same salt
+    /// and keyfile requirement. The key commitment settles it.
+    fn sealed(&self, slot: &KeySlot) -> bool {
+        slot.keyfile == self.keyfile_hash.is_some()
+            && slot.salt == general_purpose::STANDARD.encode(self.salt)
+    }
+
+    /// AES-GCM is not key-committing: a crafted slot can open under several passcodes, each
+    /// to a different data key. Every slot therefore records an HMAC of a fixed label under
+    /// a subkey of this key, which is checked before the data key is unwrapped.
+    fn commitment(&self) -> Hmac<Sha256> {
+        let mut subkey = [0u8; 32];
+        Hkdf::<Sha256>::new(None, &self.key)
+            .expand(b"tsbin/key-slot-commitment/v1", &mut subkey)
+            .expect("valid hkdf length");
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(&subkey).expect("HMAC accepts any key length");
+        subkey.zeroize();
+        mac.update(b"tsbin key slot commitment");
+        mac
+    }
+
+    /// The key as a string that `DerivedKey::import` accepts. It opens every trash the
+    /// passcode opens, so it must be kept as carefully as the passcode.
+    pub fn export(&self) -> String {
+        let exported = ExportedKey {
+            passcode_hash: self.passcode_hash.clone(),
+            salt: general_purpose::STANDARD.encode(self.salt),
+            key: general_purpose::STANDARD.encode(self.key),
+            keyfile_hash: self
+                .keyfile_hash
+                .map(|hash| general_purpose::STANDARD.encode(hash)),
+        };
+        serde_json::to_string(&exported).expect("derived key serialization")
+    }
+
+    pub fn import(exported: &str) -> Result<DerivedKey> {
+        let exported: ExportedKey =
+            serde_json::from_str(exported).map_err(|_| Error::format("Invalid derived key"))?;
+        Ok(Self {
+            salt: decode_array(&exported.salt)?,
+            key: decode_array(&exported.key)?,
+            keyfile_hash: exported
+                .keyfile_hash
+                .as_deref()
+                .map(decode_array)
+                .transpose()?,
+            passcode_hash: exported.passcode_hash,
+        })
+    }
+}
+
+pub async fn wrap_key(
+    backend: &impl CryptoBackend,
+    data_key: &[u8; 32],
+    passcode: &str,
+    keyfile_hash: Option<&[u8; 32]>,
+) -> Result<KeySlot> {
+    let kek = DerivedKey::derive(backend, passcode, utils::random_bytes(), keyfile_hash).await?;
+    wrap_key_with(backend, data_key, &kek).await
+}
+
+pub async fn wrap_key_with(
+    backend: &impl CryptoBackend,
+    data_key: &[u8; 32],
+    kek: &DerivedKey,
+) -> Result<KeySlot> {
+    let nonce_bytes = utils::random_bytes::<12>();
+    let wrapped = backend
+        .aes_gcm_seal(&kek.key, &nonce_bytes, data_key)
+        .await?;
+
+    Ok(KeySlot {
+        id: utils::generate_uuid()[..8].to_string(),
+        keyfile: kek.keyfile_hash.is_some(),
+        salt: general_purpose::STANDARD.encode(kek.salt),
+        nonce: general_purpose::STANDARD.encode(nonce_bytes),
+        wrapped_key: general_purpose::STANDARD.encode(wrapped),
+        commitment: general_purpose::STANDARD.encode(kek.commitment().finalize().into_bytes()),
+    })
+}
+
+fn decode(value: &str) -> Result<Vec<u8>> {
+    Ok(general_purpose::STANDARD.decode(value)?)
+}
+
+fn decode_array<const N: usize>(value: &str) -> Result<[u8; N]> {
+    decode(value)?
+        .try_into()
+        .map_err(|_| Error::format("Invalid key slot"))
+}
+
+pub async fn unwrap_key(
+    backend: &impl CryptoBackend,
+    slot: &KeySlot,
+    passcode: &str,
+    keyfile_hash: Option<&[u8; 32]>,
+) -> Result<[u8; 32]> {
+    let salt = decode_array(&slot.salt)?;
+    if slot.keyfile && keyfile_hash.is_none() {
+        return Err(Error::invalid("This trash requires a keyfile"));
+    }
+    let keyfile_hash = keyfile_hash.filter(|_| slot.keyfile);
+    let kek = DerivedKey::derive(backend, passcode, salt, keyfile_hash).await?;
+    unwrap_key_with(backend, slot, &kek).await
+}
+
+pub async fn unwrap_key_with(
+    backend: &impl CryptoBackend,
+    slot: &KeySlot,
+    kek: &DerivedKey,
+) -> Result<[u8; 32]> {
+    let commitment = decode(&slot.commitment)?;
+    if commitment.is_empty() {
+        return Err(Error::crypto("Key slot is missing its key commitment"));
+    }
+    kek.commitment()
+        .verify_slice(&commitment)
+        .map_err(|_| Error::crypto("Incorrect passcode"))?;
+
+    let nonce_bytes = decode_array(&slot.nonce)?;
+    let wrapped_key = decode(&slot.wrapped_key)?;
+    let data_key = backend
+        .aes_gcm_open(&kek.key, &nonce_bytes, &wrapped_key)
+        .await
+        .map_err(|_| Error::crypto("Incorrect passcode"))?;
+
+    data_key
+        .try_into()
+        .map_err(|_| Error::format("Invalid key slot"))
+}
+
+/// Reads the key slots out of a trash's encryption metadata. Trashes written with a
+/// single `wrapped_key` are returned as one slot; legacy trashes have none.
+pub fn key_slots(metadata: &serde_json::Value) -> Result<Vec<KeySlot>> {
+    let slots = if !metadata["key_slots"].is_null() {
+        serde_json::from_value(metadata["key_slots"].clone())
+    } else if !metadata["wrapped_key"].is_null() {
+        serde_json::from_value(metadata["wrapped_key"].clone()).map(|slot| vec![slot])
+    } else {
+        Ok(Vec::new())
+    };
+    Ok(slots?)
+}
+
+/// Tries the passcode on every slot and returns the index of the slot that opened along
+/// with the data key. Each distinct salt costs one key derivation, after which the key
+/// commitment rejects the slots of other passcodes before anything is decrypted.
+pub async fn open_key_slots(
+    backend: &impl CryptoBackend,
+    slots: &[KeySlot],
+    passcode: &str,
+    keyfile_hash: Option<&[u8; 32]>,
+) -> Result<(usize, [u8; 32])> {
+    if keyfile_hash.is_none() && !slots.is_empty() && slots.iter().all(|slot| slot.keyfile) {
+        return Err(Error::invalid("This trash requires a keyfile"));
+    }
+    let mut derived: Vec<DerivedKey> = Vec::new();
+    for (index, slot) in slots.iter().enumerate() {
+        if slot.keyfile && keyfile_hash.is_none() {
+            continue;
+        }
+        let Ok(salt) = decode_array(&slot.salt) else {
+            continue;
+        };
+        let keyfile_hash = keyfile_hash.filter(|_| slot.keyfile);
+        let position = match derived
+            .iter()
+            .position(|kek| kek.salt == salt && kek.keyfile_hash.as_ref() == keyfile_hash)
+        {
+            Some(position) => position,
+            None => {
+                derived.push(DerivedKey::derive(backend, passcode, salt, keyfile_hash).await?);
+                derived.len() - 1
+            }
+        };
+        if let Ok(key) = unwrap_key_with(backend, slot, &derived[position]).await {
+            return Ok((index, key));
+        }
+    }
+    Err(Error::crypto("Incorrect passcode or keyfile"))
+}
+
+/// Opens the first slot sealed with `kek`, without any key derivation.
+pub async fn open_key_slots_with(
+    backend: &impl CryptoBackend,
+    slots: &[KeySlot],
+    kek: &DerivedKey,
+) -> Result<(usize, [u8; 32])> {
+    for (index, slot) in slots
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| kek.sealed(slot))
+    {
+        if let Ok(key) = unwrap_key_with(backend, slot, kek).await {
+            return Ok((index, key));
+        }
+    }
+    Err(Error::crypto(
+        "This trash was not sealed with this derived key",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{RustCrypto, run_sync};
+
+    #[test]
+    fn each_passcode_opens_its_own_slot() {
+        let data_key = generate_data_key();
+        let slots: Vec<_> = ["first", "second"]
+            .into_iter()
+            .map(|passcode| run_sync(wrap_key(&RustCrypto, &data_key, passcode, None)).unwrap())
+            .collect();
+        assert!(
+            slots
+                .iter()
+                .all(|slot| serde_json::to_value(slot).unwrap()["passcode_hash"].is_null())
+        );
+
+        let (index, key) = run_sync(open_key_slots(&RustCrypto, &slots, "second", None)).unwrap();
+        assert_eq!((index, key), (1, data_key));
+        let (index, _) = run_sync(open_key_slots(&RustCrypto, &slots, "first", None)).unwrap();
+        assert_eq!(index, 0);
+        assert!(run_sync(open_key_slots(&RustCrypto, &slots, "third", None)).is_err());
+    }
+
+    #[test]
+    fn keyfile_slot_needs_its_keyfile() {
+        let data_key = generate_data_key();
+        let keyfile = utils::hash_keyfile(b"keyfile contents");
+        let wrong_keyfile = utils::hash_keyfile(b"other contents");
+        let slots = vec![run_sync(wrap_key(&RustCrypto, &data_key, "pw", Some(&keyfile))).unwrap()];
+        assert!(slots[0].keyfile);
+
+        assert_eq!(
+            run_sync(open_key_slots(&RustCrypto, &slots, "pw", None)).unwrap_err(),
+            Error::invalid("This trash requires a keyfile")
+        );
+        assert_eq!(
+            run_sync(open_key_slots(
+                &RustCrypto,
+                &slots,
+                "pw",
+                Some(&wrong_keyfile)
+            ))
+            .unwrap_err(),
+            Error::crypto("Incorrect passcode or keyfile")
+        );
+        assert_eq!(
+            run_sync(unwrap_key(&RustCrypto, &slots[0], "pw", None)).unwrap_err(),
+            Error::invalid("This trash requires a keyfile")
+        );
+        assert!(
+            run_sync(unwrap_key(
+                &RustCrypto,
+                &slots[0],
+                "pw",
+                Some(&wrong_keyfile)
+            ))
+            .is_err()
+        );
+
+        let (_, key) = run_sync(open_key_slots(&RustCrypto, &slots, "pw", Some(&keyfile))).unwrap();
+        assert_eq!(key, data_key);
+    }
+
+    #[test]
+    fn keyfile_slot_is_skipped_without_a_keyfile() {
+        // The same passcode with and without a keyfile seals two independent slots
+        let data_key = generate_data_key();
+        let keyfile = utils::hash_keyfile(b"keyfile contents");
+        let slots = vec![
+            run_sync(wrap_key(&RustCrypto, &data_key, "pw", Some(&keyfile))).unwrap(),
+            run_sync(wrap_key(&RustCrypto, &data_key, "pw", None)).unwrap(),
+        ];
+        let (index, _) = run_sync(open_key_slots(&RustCrypto, &slots, "pw", None)).unwrap();
+        assert_eq!(index, 1);
+        let (index, _) =
+            run_sync(open_key_slots(&RustCrypto, &slots, "pw", Some(&keyfile))).unwrap();
+        assert_eq!(index, 0);
+    }
+}
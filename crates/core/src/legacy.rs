@@ -0,0 +1,67 @@
+//! Files written by the retired Node CLI: `[iv (12)][tag (16)][ciphertext]` under
+//! AES-256-GCM with a `sha256(passphrase)` key. The plaintext carries the original file
+//! name as `[name length (u16 BE)][name][content]`.
+
+use crate::{
+    age,
+    encryptor::COMMITMENT_MAGIC,
+    error::{Error, Result},
+};
+use aes_gcm::{
+    Aes256Gcm,
+    aead::{AeadInPlace, KeyInit},
+};
+use generic_array::GenericArray;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
+
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const NAME_LEN_LEN: usize = 2;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+pub struct LegacyFile {
+    pub file_name: String,
+    pub content: Vec<u8>,
+}
+
+/// Whether `data` could be a Node CLI file. The layout has no magic bytes, so this only
+/// rules out the formats that have one; decrypting is the real test.
+pub fn is_legacy(data: &[u8]) -> bool {
+    data.len() >= IV_LEN + TAG_LEN + NAME_LEN_LEN
+        && !data.starts_with(COMMITMENT_MAGIC)
+        && !age::is_age(data)
+}
+
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<LegacyFile> {
+    if data.len() < IV_LEN + TAG_LEN + NAME_LEN_LEN {
+        return Err(Error::format("Encrypted file too small / invalid"));
+    }
+    let (iv, rest) = data.split_at(IV_LEN);
+    let (tag, ciphertext) = rest.split_at(TAG_LEN);
+
+    let key = Zeroizing::new(<[u8; 32]>::from(Sha256::digest(passphrase.as_bytes())));
+    let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+    Aes256Gcm::new(&(*key).into())
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(iv).as_0_14(),
+            b"",
+            &mut plaintext,
+            GenericArray::from_slice(tag).as_0_14(),
+        )
+        .map_err(|_| Error::crypto("Wrong passcode or corrupted file"))?;
+
+    let name_len = u16::from_be_bytes([plaintext[0], plaintext[1]]) as usize;
+    let name = plaintext
+        .get(NAME_LEN_LEN..NAME_LEN_LEN + name_len)
+        .ok_or_else(|| Error::format("Invalid file name"))?;
+    let file_name =
+        String::from_utf8(name.to_vec()).map_err(|_| Error::format("Invalid file name"))?;
+
+    Ok(LegacyFile {
+        file_name,
+        content: plaintext[NAME_LEN_LEN + name_len..].to_vec(),
+    })
+}
@@ -0,0 +1,27 @@
+//! Encryption, key management and the trash format of tsbin, independent of the platform
+//! it runs on. `tsbin-wasm` exposes it to browsers; native tools use it directly.
+
+pub mod age;
+pub mod backend;
+pub mod client;
+pub mod encryptor;
+pub mod error;
+pub mod keywrap;
+pub mod legacy;
+pub mod manifest;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod rawkey;
+pub mod recipient;
+pub mod shamir;
+pub mod signing;
+pub mod transport;
+pub mod trash;
+pub mod types;
+pub mod utils;
+
+pub use client::TsbinClient;
+pub use encryptor::{Cipher, Encryptor};
+pub use error::{Error, Result};
+pub use keywrap::{DerivedKey, KeySlot};
+pub use manifest::Manifest;
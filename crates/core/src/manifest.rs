@@ -0,0 +1,146 @@
+use crate::error::{Error, Result};
+use base64::{Engine, engine::general_purpose};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// What a trash signature and manifest MAC cover: the public metadata fields and the hash of every
+/// ciphertext chunk in upload order. Key slots are left out so passcodes can be changed
+/// without invalidating the signature.
+#[derive(Debug, Clone, Serialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub encryption_type: Option<String>,
+    pub expire_at: Option<String>,
+    pub original_size: Option<u64>,
+    pub total_chunks: Option<u64>,
+    pub chunk_size: Option<u64>,
+    pub filename: Option<String>,
+    pub mime_type: Option<String>,
+    pub chunk_hashes: Vec<String>,
+}
+
+impl Manifest {
+    pub fn new(metadata: &serde_json::Value, chunk_hashes: Vec<String>) -> Self {
+        let string = |field: &str| metadata[field].as_str().map(str::to_string);
+        Self {
+            version: 1,
+            encryption_type: string("encryption_type"),
+            expire_at: string("expire_at"),
+            original_size: metadata["original_size"]
+                .as_u64()
+                .or_else(|| metadata["original_length"].as_u64()),
+            total_chunks: metadata["total_chunks"].as_u64(),
+            chunk_size: metadata["chunk_size"].as_u64(),
+            filename: string("filename"),
+            mime_type: string("mime_type"),
+            chunk_hashes,
+        }
+    }
+
+    pub fn chunk_hash(chunk: &[u8]) -> String {
+        general_purpose::STANDARD.encode(Sha256::digest(chunk))
+    }
+
+    /// The canonical encoding that gets signed; field order is fixed by the struct.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("manifest serialization")
+    }
+
+    /// HMAC over the manifest under a subkey of the trash data key, so only someone who
+    /// can open the trash could have produced it.
+    fn hmac(&self, data_key: &[u8]) -> Hmac<Sha256> {
+        let mut mac_key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, data_key)
+            .expand(b"tsbin/manifest-mac/v1", &mut mac_key)
+            .expect("valid hkdf length");
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(&mac_key).expect("HMAC accepts any key length");
+        mac.update(&self.to_bytes());
+        mac
+    }
+
+    pub fn mac(&self, data_key: &[u8]) -> String {
+        general_purpose::STANDARD.encode(self.hmac(data_key).finalize().into_bytes())
+    }
+
+    pub fn verify_mac(&self, data_key: &[u8], mac: &str) -> Result<()> {
+        let mac = general_purpose::STANDARD.decode(mac)?;
+        self.hmac(data_key)
+            .verify_slice(&mac)
+            .map_err(|_| Error::crypto("Trash metadata does not match its content"))
+    }
+}
+
+/// Stores the manifest MAC, keyed from the data key, that `verify` checks.
+pub fn authenticate(metadata: &mut serde_json::Value, chunk_hashes: &[String], data_key: &[u8]) {
+    let manifest = Manifest::new(metadata, chunk_hashes.to_vec());
+    metadata["manifest_mac"] = serde_json::json!(manifest.mac(data_key));
+}
+
+/// Checks the public metadata and the order and content of the downloaded chunks
+/// against the manifest MAC. Returns the manifest once it is authenticated, or `None`
+/// for legacy metadata written without one.
+///
+/// `required` is set for every ciphertext with a key commitment, which has always been
+/// written along with a MAC. It must not be read from the metadata, since the server
+/// could then drop the MAC along with whatever fields ask for it.
+pub fn verify(
+    metadata: Option<&serde_json::Value>,
+    chunk_hashes: &[String],
+    data_key: &[u8],
+    required: bool,
+) -> Result<Option<Manifest>> {
+    let Some(mac) = metadata.and_then(|metadata| metadata["manifest_mac"].as_str()) else {
+        return match required {
+            true => Err(Error::crypto("Trash metadata is missing its manifest MAC")),
+            false => Ok(None),
+        };
+    };
+    let manifest = Manifest::new(metadata.unwrap(), chunk_hashes.to_vec());
+    manifest.verify_mac(data_key, mac)?;
+    Ok(Some(manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn missing_mac_is_rejected_when_required() {
+        let chunk_hashes = vec![Manifest::chunk_hash(b"sealed")];
+        let data_key = [7; 32];
+        let mut metadata = json!({ "key_slots": [], "key_commitment": true });
+        authenticate(&mut metadata, &chunk_hashes, &data_key);
+        assert!(
+            verify(Some(&metadata), &chunk_hashes, &data_key, true)
+                .unwrap()
+                .is_some()
+        );
+
+        // Whatever else the server strips, the MAC is still required
+        for stripped in [
+            json!({ "key_slots": [], "key_commitment": true }),
+            json!({ "encryption_type": "aes256gcm" }),
+        ] {
+            assert_eq!(
+                verify(Some(&stripped), &chunk_hashes, &data_key, true).unwrap_err(),
+                Error::crypto("Trash metadata is missing its manifest MAC")
+            );
+        }
+        assert!(verify(None, &chunk_hashes, &data_key, true).is_err());
+    }
+
+    #[test]
+    fn legacy_metadata_may_go_without_a_mac() {
+        let legacy = json!({ "passcode_hash": "0000", "encryption_type": "aes256gcm" });
+        assert!(
+            verify(Some(&legacy), &[], &[7; 32], false)
+                .unwrap()
+                .is_none()
+        );
+        assert!(verify(None, &[], &[7; 32], false).unwrap().is_none());
+    }
+}
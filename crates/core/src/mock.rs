@@ -0,0 +1,552 @@
+//! An in-memory tsbin API served as a `Transport`, for tests that need a whole upload and
+//! download without the real server or Telegram behind it.
+//!
+//! It answers `/trash/text`, `/trash/file`, `/trash/chunk` and `/trash/:id` the way the
+//! NestJS API does, including its error bodies, and can be told to misbehave with `Fault`s.
+
+pub mod fixtures;
+
+use crate::{
+    error::Result,
+    transport::{Body, Part, Request, Response, Transport},
+};
+use serde_json::{Value, json};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Ways the server can misbehave. One-shot faults are used up by the first request they
+/// hit; inject them again to hit several.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// The next upload of this chunk index gets no response at all
+    DropChunk(u32),
+    /// The next request whose path starts with this one answers 500 (one-shot)
+    ServerError(&'static str),
+    /// Every response is held back for this many polls
+    Delay(u32),
+    /// File trashes list their chunks in reverse order
+    ReorderChunks,
+}
+
+struct StoredTrash {
+    kind: &'static str,
+    object_id: String,
+    passcode_hash: String,
+    slot_passcode_hashes: BTreeMap<String, String>,
+    owner_token: Option<String>,
+    metadata: Value,
+}
+
+#[derive(Default)]
+struct State {
+    /// By slug
+    trashes: HashMap<String, StoredTrash>,
+    /// Text and file trash objects, by object id
+    objects: HashMap<String, Value>,
+    /// By Telegram file id
+    chunks: HashMap<String, Vec<u8>>,
+    faults: Vec<Fault>,
+    next_id: u32,
+}
+
+impl State {
+    fn id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}_{:08}", prefix, self.next_id)
+    }
+
+    /// Takes the first one-shot fault matching `hit`.
+    fn take_fault(&mut self, hit: impl Fn(&Fault) -> bool) -> bool {
+        match self.faults.iter().position(hit) {
+            Some(index) => {
+                self.faults.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn has_fault(&self, fault: &Fault) -> bool {
+        self.faults.contains(fault)
+    }
+
+    fn delay(&self) -> u32 {
+        self.faults
+            .iter()
+            .map(|fault| match fault {
+                Fault::Delay(polls) => *polls,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+#[derive(Default)]
+pub struct MockServer {
+    state: RefCell<State>,
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inject(&self, fault: Fault) {
+        self.state.borrow_mut().faults.push(fault);
+    }
+
+    pub fn clear_faults(&self) {
+        self.state.borrow_mut().faults.clear();
+    }
+
+    /// The number of chunks stored so far, including those of abandoned uploads.
+    pub fn chunk_count(&self) -> usize {
+        self.state.borrow().chunks.len()
+    }
+
+    /// The stored encryption metadata of a trash, by slug.
+    pub fn metadata(&self, slug: &str) -> Option<Value> {
+        let state = self.state.borrow();
+        state.trashes.get(slug).map(|trash| trash.metadata.clone())
+    }
+
+    /// Overwrites a stored chunk, as a server tampering with content would.
+    pub fn tamper_chunk(&self, file_id: &str, data: Vec<u8>) {
+        self.state
+            .borrow_mut()
+            .chunks
+            .insert(file_id.to_string(), data);
+    }
+
+    /// Overwrites the stored encryption metadata of a trash, as a server tampering with it
+    /// would.
+    pub fn tamper_metadata(&self, slug: &str, metadata: Value) {
+        if let Some(trash) = self.state.borrow_mut().trashes.get_mut(slug) {
+            trash.metadata = metadata;
+        }
+    }
+
+    /// Serves one request against the in-memory storage.
+    fn route(&self, request: Request) -> Response {
+        let (path, query) = split_url(&request.url);
+        let state = &mut *self.state.borrow_mut();
+        if state.take_fault(
+            |fault| matches!(fault, Fault::ServerError(prefix) if path.starts_with(prefix)),
+        ) {
+            return error(500, "Internal server error", &path);
+        }
+
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        match (request.method, segments.as_slice()) {
+            ("POST", ["trash", "text"]) => create_text(state, &request.body),
+            ("GET", ["trash", "text"]) => get_text(state, &query, &path),
+            ("POST", ["trash", "file"]) => create_file(state, &request.body, &path),
+            ("GET", ["trash", "file"]) => get_file(state, &query, &path),
+            ("POST", ["trash", "chunk"]) => upload_chunk(state, &request.body, &path),
+            ("GET", ["trash", "chunk"]) => download_chunk(state, &query, &path),
+            ("GET", ["trash", slug]) => find_trash(state, slug, &path),
+            ("PATCH", ["trash", slug]) => update_trash(state, slug, &request.body, &path),
+            ("DELETE", ["trash", slug]) => delete_trash(state, slug, &query, &path),
+            _ => error(404, &format!("Cannot {} {}", request.method, path), &path),
+        }
+    }
+}
+
+impl Transport for MockServer {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let (dropped, delay) = {
+            let state = &mut *self.state.borrow_mut();
+            let dropped = match (&request.body, request.url.contains("/trash/chunk")) {
+                (Body::Multipart(parts), true) => chunk_index(parts).is_some_and(|index| {
+                    state.take_fault(|fault| *fault == Fault::DropChunk(index))
+                }),
+                _ => false,
+            };
+            (dropped, state.delay())
+        };
+        Delay(delay).await;
+        if dropped {
+            return Err(crate::Error::Transport("Connection reset".to_string()));
+        }
+        Ok(self.route(request))
+    }
+}
+
+/// Pending for a number of polls, waking itself every time.
+struct Delay(u32);
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 == 0 {
+            return Poll::Ready(());
+        }
+        self.0 -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+fn respond(status: u16, body: Value) -> Response {
+    Response {
+        status,
+        body: body.to_string().into_bytes(),
+    }
+}
+
+/// An error body as the API's exception filter writes it.
+fn error(status: u16, message: &str, path: &str) -> Response {
+    respond(
+        status,
+        json!({
+            "success": false,
+            "message": message,
+            "data": null,
+            "path": path,
+            "timestamp": "2025-01-01T00:00:00.000Z",
+        }),
+    )
+}
+
+fn split_url(url: &str) -> (String, HashMap<String, String>) {
+    let path_start = url.find("/trash").unwrap_or(url.len());
+    let url = &url[path_start..];
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (name.to_string(), urldecode(value)))
+        .collect();
+    (path.to_string(), query)
+}
+
+fn urldecode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' => value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn json_body(body: &Body) -> Value {
+    match body {
+        Body::Json(body) => body.clone(),
+        _ => Value::Null,
+    }
+}
+
+fn chunk_index(parts: &[Part]) -> Option<u32> {
+    let part = parts.iter().find(|part| part.name == "chunk_index")?;
+    std::str::from_utf8(&part.data).ok()?.parse().ok()
+}
+
+/// A trash opens with its main passcode or with the passcode of any of its key slots.
+fn passcode_valid(trash: &StoredTrash, passcode: Option<&str>) -> bool {
+    if trash.passcode_hash == "0000" || Some(trash.passcode_hash.as_str()) == passcode {
+        return true;
+    }
+    passcode.is_some_and(|passcode| {
+        trash
+            .slot_passcode_hashes
+            .values()
+            .any(|hash| hash == passcode)
+    })
+}
+
+/// Only the owner token changes or deletes a trash. Those created without one fall back
+/// to their passcode, which public ones do not have.
+fn owner_valid(trash: &StoredTrash, owner_token: Option<&str>, passcode: Option<&str>) -> bool {
+    match &trash.owner_token {
+        Some(token) => owner_token == Some(token.as_str()),
+        None => trash.passcode_hash != "0000" && passcode_valid(trash, passcode),
+    }
+}
+
+/// The metadata without any passcode hash, as the API stores and serves it.
+fn public_metadata(mut metadata: Value) -> Value {
+    if let Some(metadata) = metadata.as_object_mut() {
+        metadata.remove("passcode_hash");
+    }
+    if let Some(slots) = metadata.get_mut("key_slots").and_then(Value::as_array_mut) {
+        for slot in slots.iter_mut().filter_map(Value::as_object_mut) {
+            slot.remove("passcode_hash");
+        }
+    }
+    metadata
+}
+
+fn slot_passcode_hashes(value: &Value) -> BTreeMap<String, String> {
+    serde_json::from_value(value.clone()).unwrap_or_default()
+}
+
+fn store_trash(state: &mut State, kind: &'static str, object: Value, body: &Value) -> String {
+    let slug = state.id("ts");
+    let object_id = state.id(if kind == "TEXT" { "tt" } else { "ft" });
+    let mut object = object;
+    object["id"] = json!(object_id);
+    if kind == "FILE" {
+        object["trash_id"] = json!(object_id);
+    }
+    state.objects.insert(object_id.clone(), object);
+    let metadata = &body["encryption_metadata"];
+    state.trashes.insert(
+        slug.clone(),
+        StoredTrash {
+            kind,
+            object_id,
+            passcode_hash: body["passcode_hash"]
+                .as_str()
+                .or(metadata["passcode_hash"].as_str())
+                .unwrap_or("0000")
+                .to_string(),
+            slot_passcode_hashes: slot_passcode_hashes(&body["slot_passcode_hashes"]),
+            owner_token: body["owner_token"].as_str().map(str::to_string),
+            metadata: public_metadata(metadata.clone()),
+        },
+    );
+    slug
+}
+
+fn create_text(state: &mut State, body: &Body) -> Response {
+    let body = json_body(body);
+    let metadata = body["encryption_metadata"].clone();
+    let object = json!({
+        "enc_trash_text": body["enc_trash_text"],
+        "encryption_type": metadata["encryption_type"],
+        "text_length": body["text_length"],
+    });
+    let slug = store_trash(state, "TEXT", object, &body);
+    respond(
+        201,
+        json!({ "success": true, "data": { "trash_id": slug } }),
+    )
+}
+
+/// The trash owning an object, by object id.
+fn owner<'a>(state: &'a State, object_id: &str) -> Option<&'a StoredTrash> {
+    state
+        .trashes
+        .values()
+        .find(|trash| trash.object_id == object_id)
+}
+
+fn get_text(state: &State, query: &HashMap<String, String>, path: &str) -> Response {
+    let id = query.get("id").map(String::as_str).unwrap_or_default();
+    let (Some(object), Some(trash)) = (state.objects.get(id), owner(state, id)) else {
+        return error(500, "Trash not found", path);
+    };
+    if !passcode_valid(trash, query.get("passcode").map(String::as_str)) {
+        return error(403, "Passcode is incorrect", path);
+    }
+    let mut data = object.clone();
+    data["encryption_metadata"] = trash.metadata.clone();
+    respond(200, json!({ "success": true, "data": data }))
+}
+
+fn create_file(state: &mut State, body: &Body, path: &str) -> Response {
+    let body = json_body(body);
+    let metadata = body["encryption_metadata"].clone();
+    if metadata.is_null() || body["file_ids"].as_array().is_none_or(Vec::is_empty) {
+        return error(
+            500,
+            "Missing required fields: encryption_metadata, file_ids",
+            path,
+        );
+    }
+    let object = json!({
+        "file_ids": body["file_ids"],
+        "message_ids": body["message_ids"],
+        "total_chunks": metadata["total_chunks"],
+        "total_size": metadata["original_size"],
+        "file_name": metadata["filename"],
+        "mime_type": metadata["mime_type"].as_str().filter(|mime| !mime.is_empty()).unwrap_or("application/octet-stream"),
+        "file_size": metadata["original_size"],
+    });
+    let slug = store_trash(state, "FILE", object, &body);
+    respond(
+        201,
+        json!({ "success": true, "data": { "trash_id": slug } }),
+    )
+}
+
+fn get_file(state: &State, query: &HashMap<String, String>, path: &str) -> Response {
+    let id = query.get("file_id").map(String::as_str).unwrap_or_default();
+    let (Some(object), Some(trash)) = (state.objects.get(id), owner(state, id)) else {
+        return error(500, "Trash not found", path);
+    };
+    let mut data = object.clone();
+    data["trash_type"] = json!(trash.kind);
+    data["encrypted"] = json!(trash.passcode_hash != "0000");
+    data["expire_at"] = trash.metadata["expire_at"].clone();
+    data["encryption_metadata"] = trash.metadata.clone();
+    if state.has_fault(&Fault::ReorderChunks)
+        && let Some(file_ids) = data["file_ids"].as_array_mut()
+    {
+        file_ids.reverse();
+    }
+    respond(200, json!({ "success": true, "data": data }))
+}
+
+fn upload_chunk(state: &mut State, body: &Body, path: &str) -> Response {
+    let Body::Multipart(parts) = body else {
+        return error(500, "No chunk provided", path);
+    };
+    let Some(chunk) = parts.iter().find(|part| part.name == "chunk") else {
+        return error(500, "No chunk provided", path);
+    };
+    let file_id = state.id("tg");
+    let message_id = state.next_id;
+    state.chunks.insert(file_id.clone(), chunk.data.clone());
+    respond(
+        201,
+        json!({
+            "success": true,
+            "message_id": message_id,
+            "file_id": file_id,
+            "chunk_index": chunk_index(parts),
+        }),
+    )
+}
+
+fn download_chunk(state: &State, query: &HashMap<String, String>, path: &str) -> Response {
+    let (Some(trash_id), Some(file_id)) = (query.get("trash_id"), query.get("file_id")) else {
+        return error(400, "Missing file_id or trash_id", path);
+    };
+    if !state.objects.contains_key(trash_id) {
+        return error(404, "Trash not found", path);
+    }
+    match state.chunks.get(file_id) {
+        Some(chunk) => Response {
+            status: 200,
+            body: chunk.clone(),
+        },
+        None => error(500, "Failed to stream file", path),
+    }
+}
+
+fn find_trash(state: &State, slug: &str, path: &str) -> Response {
+    let Some(trash) = state.trashes.get(slug) else {
+        return error(
+            500,
+            "DB: Failed to find trash by ID - Trash not found",
+            path,
+        );
+    };
+    respond(
+        200,
+        json!({
+            "success": true,
+            "data": {
+                "badReport": 0,
+                "createdAt": "2025-01-01T00:00:00.000Z",
+                "encrypted": trash.passcode_hash != "0000",
+                "expireAt": trash.metadata["expire_at"],
+                "id": format!("obj_{}", slug),
+                "slug": slug,
+                "type": trash.kind,
+                "objectId": trash.object_id,
+            },
+            "message": "Trash retrieved successfully",
+        }),
+    )
+}
+
+fn update_trash(state: &mut State, slug: &str, body: &Body, path: &str) -> Response {
+    let body = json_body(body);
+    let Some(trash) = state.trashes.get_mut(slug) else {
+        return error(404, "Trash not found", path);
+    };
+    if !owner_valid(
+        trash,
+        body["owner_token"].as_str(),
+        body["passcode"].as_str(),
+    ) {
+        return error(403, "Owner token is incorrect", path);
+    }
+    // Like the API, only the key slots are taken from the request
+    let Some(key_slots) = body["encryption_metadata"]["key_slots"]
+        .as_array()
+        .filter(|slots| !slots.is_empty())
+    else {
+        return error(
+            400,
+            "Missing required fields: encryption_metadata.key_slots",
+            path,
+        );
+    };
+    let mut known = std::mem::take(&mut trash.slot_passcode_hashes);
+    known.extend(slot_passcode_hashes(&body["slot_passcode_hashes"]));
+    let slot_ids: Vec<&str> = key_slots
+        .iter()
+        .filter_map(|slot| slot["id"].as_str())
+        .collect();
+    trash.slot_passcode_hashes = known
+        .into_iter()
+        .filter(|(id, _)| slot_ids.contains(&id.as_str()))
+        .collect();
+    // The first slot holds the main passcode
+    if let Some(hash) = slot_ids
+        .first()
+        .and_then(|id| trash.slot_passcode_hashes.get(*id))
+        .or_else(|| trash.slot_passcode_hashes.values().next())
+    {
+        trash.passcode_hash = hash.clone();
+    }
+    trash.metadata["key_slots"] = json!(key_slots);
+    trash.metadata = public_metadata(std::mem::take(&mut trash.metadata));
+    if let Some(metadata) = trash.metadata.as_object_mut() {
+        metadata.remove("wrapped_key");
+    }
+    respond(
+        200,
+        json!({ "success": true, "message": "Trash metadata updated successfully" }),
+    )
+}
+
+fn delete_trash(
+    state: &mut State,
+    slug: &str,
+    query: &HashMap<String, String>,
+    path: &str,
+) -> Response {
+    let Some(trash) = state.trashes.get(slug) else {
+        return error(404, "Trash not found", path);
+    };
+    if !owner_valid(
+        trash,
+        query.get("owner_token").map(String::as_str),
+        query.get("passcode").map(String::as_str),
+    ) {
+        return error(403, "Owner token is incorrect", path);
+    }
+    if let Some(trash) = state.trashes.remove(slug) {
+        state.objects.remove(&trash.object_id);
+    }
+    respond(
+        200,
+        json!({ "success": true, "message": "Trash deleted successfully" }),
+    )
+}
@@ -0,0 +1,41 @@
+//! The setup shared by every test that runs against the mock API, in this crate and in
+//! the wasm bindings and CLI.
+
+use super::MockServer;
+use crate::{
+    TsbinClient,
+    backend::CryptoBackend,
+    transport::Transport,
+    trash::{SealOptions, Session},
+};
+
+pub const BASE_URL: &str = "http://mock/v1";
+
+/// A client of a fresh mock API with nothing stored.
+pub fn client() -> TsbinClient<MockServer> {
+    TsbinClient::new(BASE_URL.to_string(), String::new(), MockServer::new())
+}
+
+/// A session against a fresh mock API with nothing stored.
+pub fn session<B: CryptoBackend>(backend: B) -> Session<MockServer, B> {
+    Session::new(client(), backend)
+}
+
+/// The default seal options, uploading in chunks of `chunk_size` bytes.
+pub fn options(chunk_size: usize) -> SealOptions<'static> {
+    SealOptions {
+        chunk_size,
+        ..SealOptions::default()
+    }
+}
+
+/// `len` bytes whose pattern does not line up with chunk boundaries.
+pub fn content(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// The object id a trash slug resolves to, which its content is fetched by.
+pub async fn object_id<T: Transport>(client: &TsbinClient<T>, slug: &str) -> String {
+    let trash = client.get_trash(slug).await.unwrap();
+    trash.object_id.unwrap()
+}
@@ -0,0 +1,110 @@
+//! Raw 256-bit data keys handed in by callers that manage their own keys, either as
+//! base64 or as an AES-GCM JSON Web Key.
+
+use crate::error::{Error, Result};
+use base64::{
+    Engine,
+    engine::general_purpose::{self, URL_SAFE_NO_PAD},
+};
+use serde::{Deserialize, Serialize};
+
+/// The subset of an RFC 7517 symmetric JWK that WebCrypto `exportKey("jwk")` produces.
+#[derive(Debug, Serialize, Deserialize)]
+struct Jwk {
+    kty: String,
+    k: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alg: Option<String>,
+    #[serde(default)]
+    ext: bool,
+    #[serde(default)]
+    key_ops: Vec<String>,
+}
+
+/// Parses a raw key given as base64 or as a JWK string.
+pub fn parse_key(key: &str) -> Result<[u8; 32]> {
+    let key = key.trim();
+    let bytes = if key.starts_with('{') {
+        let jwk: Jwk = serde_json::from_str(key)?;
+        if jwk.kty != "oct" || jwk.alg.as_deref().is_some_and(|alg| alg != "A256GCM") {
+            return Err(Error::invalid("Only A256GCM oct keys are supported"));
+        }
+        URL_SAFE_NO_PAD.decode(jwk.k.trim_end_matches('='))
+    } else {
+        general_purpose::STANDARD.decode(key)
+    };
+    from_bytes(&bytes?)
+}
+
+pub fn from_bytes(key: &[u8]) -> Result<[u8; 32]> {
+    key.try_into()
+        .map_err(|_| Error::format("Raw keys must be 256 bits"))
+}
+
+pub fn to_base64(key: &[u8]) -> String {
+    general_purpose::STANDARD.encode(key)
+}
+
+/// Encodes a key the way WebCrypto exports an extractable AES-GCM key.
+pub fn to_jwk(key: &[u8]) -> String {
+    let jwk = Jwk {
+        kty: "oct".to_string(),
+        k: URL_SAFE_NO_PAD.encode(key),
+        alg: Some("A256GCM".to_string()),
+        ext: true,
+        key_ops: vec!["encrypt".to_string(), "decrypt".to_string()],
+    };
+    serde_json::to_string(&jwk).expect("jwk serialization")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [0xfe; 32];
+
+    #[test]
+    fn keys_round_trip_through_base64_and_jwk() {
+        assert_eq!(parse_key(&to_base64(&KEY)).unwrap(), KEY);
+        assert_eq!(parse_key(&to_jwk(&KEY)).unwrap(), KEY);
+
+        let jwk: serde_json::Value = serde_json::from_str(&to_jwk(&KEY)).unwrap();
+        assert_eq!(jwk["kty"], "oct");
+        assert_eq!(jwk["alg"], "A256GCM");
+        // What WebCrypto exports, with padding and without `alg`, is read as well
+        let padded = format!(r#"{{"kty":"oct","k":"{}="}}"#, URL_SAFE_NO_PAD.encode(KEY));
+        assert_eq!(parse_key(&padded).unwrap(), KEY);
+    }
+
+    #[test]
+    fn other_jwk_keys_are_rejected() {
+        let k = URL_SAFE_NO_PAD.encode(KEY);
+        for jwk in [
+            format!(r#"{{"kty":"RSA","k":"{}"}}"#, k),
+            format!(r#"{{"kty":"oct","k":"{}","alg":"A128GCM"}}"#, k),
+        ] {
+            assert_eq!(
+                parse_key(&jwk).unwrap_err(),
+                Error::invalid("Only A256GCM oct keys are supported")
+            );
+        }
+        assert!(parse_key(r#"{"kty":"oct"}"#).is_err());
+    }
+
+    #[test]
+    fn keys_of_the_wrong_length_are_rejected() {
+        let wrong_length = Error::format("Raw keys must be 256 bits");
+        assert_eq!(parse_key(&to_base64(&[1; 16])).unwrap_err(), wrong_length);
+        assert_eq!(parse_key(&to_base64(&[1; 33])).unwrap_err(), wrong_length);
+        assert_eq!(parse_key(&to_jwk(&[1; 16])).unwrap_err(), wrong_length);
+        assert_eq!(from_bytes(&[]).unwrap_err(), wrong_length);
+    }
+
+    #[test]
+    fn bad_base64_is_rejected() {
+        for key in ["not base64!", &URL_SAFE_NO_PAD.encode([0xfb; 32])] {
+            assert!(parse_key(key).is_err(), "{}", key);
+        }
+        assert!(parse_key(r#"{"kty":"oct","k":"not base64!"}"#).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{
+    error::{Error, Result},
+    utils,
+};
+use aes_gcm::{
+    Aes256Gcm,
+    aead::{Aead, KeyInit},
+};
+use base64::{Engine, engine::general_purpose};
+use bech32::{FromBase32, ToBase32, Variant};
+use generic_array::GenericArray;
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const PUBLIC_KEY_HRP: &str = "age";
+const SECRET_KEY_HRP: &str = "age-secret-key-";
+const WRAP_LABEL: &[u8] = b"tsbin/x25519-recipient/v1";
+
+/// The trash data key sealed to one X25519 recipient. Binary fields are base64 encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecipientSlot {
+    pub id: String,
+    pub ephemeral_key: String,
+    pub wrapped_key: String,
+}
+
+pub fn encode_public_key(public_key: &PublicKey) -> String {
+    bech32::encode(
+        PUBLIC_KEY_HRP,
+        public_key.as_bytes().to_base32(),
+        Variant::Bech32,
+    )
+    .expect("valid bech32 prefix")
+}
+
+pub fn encode_secret_key(secret: &StaticSecret) -> String {
+    bech32::encode(
+        SECRET_KEY_HRP,
+        secret.as_bytes().to_base32(),
+        Variant::Bech32,
+    )
+    .expect("valid bech32 prefix")
+    .to_uppercase()
+}
+
+fn decode_key(encoded: &str, expected_hrp: &str) -> Result<[u8; 32]> {
+    let (hrp, data, variant) = bech32::decode(encoded.trim())?;
+    if hrp != expected_hrp || variant != Variant::Bech32 {
+        return Err(Error::format("Invalid key encoding"));
+    }
+    Vec::<u8>::from_base32(&data)?
+        .try_into()
+        .map_err(|_| Error::format("Invalid key length"))
+}
+
+pub fn parse_public_key(encoded: &str) -> Result<PublicKey> {
+    decode_key(encoded, PUBLIC_KEY_HRP).map(PublicKey::from)
+}
+
+pub fn parse_secret_key(encoded: &str) -> Result<StaticSecret> {
+    decode_key(encoded, SECRET_KEY_HRP).map(StaticSecret::from)
+}
+
+/// Derives the key-wrapping key shared between the ephemeral and recipient keys.
+fn wrapping_key(
+    shared_secret: &[u8; 32],
+    ephemeral: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<[u8; 32]> {
+    if shared_secret.iter().all(|&b| b == 0) {
+        return Err(Error::format("Invalid recipient key"));
+    }
+    let mut salt = Vec::with_capacity(64);
+    salt.extend_from_slice(ephemeral.as_bytes());
+    salt.extend_from_slice(recipient.as_bytes());
+
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(WRAP_LABEL, &mut key)
+        .expect("valid HKDF output length");
+    Ok(key)
+}
+
+pub fn wrap_for_recipient(data_key: &[u8; 32], recipient: &str) -> Result<RecipientSlot> {
+    let recipient = parse_public_key(recipient)?;
+    let ephemeral_secret = StaticSecret::from(utils::random_bytes::<32>());
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let shared = ephemeral_secret.diffie_hellman(&recipient);
+    let kek = wrapping_key(shared.as_bytes(), &ephemeral, &recipient)?;
+
+    // The wrapping key is fresh for every slot, so a zero nonce is never reused
+    let cipher = Aes256Gcm::new(&kek.into());
+    let nonce = GenericArray::from_slice(&[0u8; 12]);
+    let wrapped = cipher
+        .encrypt(nonce.as_0_14(), data_key.as_slice())
+        .expect("encryption failure");
+
+    Ok(RecipientSlot {
+        id: utils::generate_uuid()[..8].to_string(),
+        ephemeral_key: general_purpose::STANDARD.encode(ephemeral.as_bytes()),
+        wrapped_key: general_purpose::STANDARD.encode(wrapped),
+    })
+}
+
+pub fn unwrap_with_identity(slot: &RecipientSlot, identity: &StaticSecret) -> Result<[u8; 32]> {
+    let decode = |value: &str| general_purpose::STANDARD.decode(value);
+    let ephemeral: [u8; 32] = decode(&slot.ephemeral_key)?
+        .try_into()
+        .map_err(|_| Error::format("Invalid recipient slot"))?;
+    let ephemeral = PublicKey::from(ephemeral);
+    let wrapped_key = decode(&slot.wrapped_key)?;
+
+    let shared = identity.diffie_hellman(&ephemeral);
+    let kek = wrapping_key(shared.as_bytes(), &ephemeral, &PublicKey::from(identity))?;
+    let cipher = Aes256Gcm::new(&kek.into());
+    let nonce = GenericArray::from_slice(&[0u8; 12]);
+    let data_key = cipher
+        .decrypt(nonce.as_0_14(), wrapped_key.as_slice())
+        .map_err(|_| Error::crypto("Key is not a recipient of this trash"))?;
+
+    data_key
+        .try_into()
+        .map_err(|_| Error::format("Invalid recipient slot"))
+}
+
+/// Reads the recipient slots out of a trash's encryption metadata.
+pub fn recipient_slots(metadata: &serde_json::Value) -> Result<Vec<RecipientSlot>> {
+    if metadata["recipients"].is_null() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_value(metadata["recipients"].clone())?)
+}
+
+/// Tries the secret key against every recipient slot; slots do not name their recipient.
+pub fn open_recipient_slots(slots: &[RecipientSlot], secret_key: &str) -> Result<[u8; 32]> {
+    let identity = parse_secret_key(secret_key)?;
+    slots
+        .iter()
+        .find_map(|slot| unwrap_with_identity(slot, &identity).ok())
+        .ok_or_else(|| Error::crypto("Key is not a recipient of this trash"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keypair(seed: u8) -> (String, String) {
+        let secret = StaticSecret::from([seed; 32]);
+        (
+            encode_public_key(&PublicKey::from(&secret)),
+            encode_secret_key(&secret),
+        )
+    }
+
+    #[test]
+    fn only_the_recipient_opens_its_slot() {
+        let data_key = [7; 32];
+        let (alice, alice_secret) = keypair(1);
+        let (bob, bob_secret) = keypair(2);
+        let (_, eve_secret) = keypair(3);
+        let slots = vec![
+            wrap_for_recipient(&data_key, &alice).unwrap(),
+            wrap_for_recipient(&data_key, &bob).unwrap(),
+        ];
+
+        assert_eq!(
+            open_recipient_slots(&slots, &alice_secret).unwrap(),
+            data_key
+        );
+        assert_eq!(open_recipient_slots(&slots, &bob_secret).unwrap(), data_key);
+        assert_eq!(
+            open_recipient_slots(&slots, &eve_secret).unwrap_err(),
+            Error::crypto("Key is not a recipient of this trash")
+        );
+        // A public key is not a secret key
+        assert!(open_recipient_slots(&slots, &alice).is_err());
+    }
+
+    #[test]
+    fn keys_round_trip_through_bech32() {
+        let secret = StaticSecret::from([1; 32]);
+        let public = PublicKey::from(&secret);
+        let (encoded_public, encoded_secret) = keypair(1);
+        assert!(encoded_public.starts_with("age1"));
+        assert!(encoded_secret.starts_with("AGE-SECRET-KEY-1"));
+
+        assert_eq!(parse_public_key(&encoded_public).unwrap(), public);
+        assert_eq!(
+            parse_secret_key(&encoded_secret).unwrap().to_bytes(),
+            secret.to_bytes()
+        );
+        // age writes secret keys in upper case, but either case is read
+        assert_eq!(
+            parse_secret_key(&encoded_secret.to_lowercase())
+                .unwrap()
+                .to_bytes(),
+            secret.to_bytes()
+        );
+    }
+
+    #[test]
+    fn malformed_keys_are_rejected() {
+        let (public, secret) = keypair(1);
+        // Each kind of key is only read as itself
+        assert!(parse_public_key(&secret).is_err());
+        assert!(parse_secret_key(&public).is_err());
+
+        let mut corrupted = public.clone().into_bytes();
+        let last = corrupted.len() - 1;
+        corrupted[last] = if corrupted[last] == b'q' { b'p' } else { b'q' };
+        assert!(parse_public_key(std::str::from_utf8(&corrupted).unwrap()).is_err());
+
+        let short = bech32::encode(PUBLIC_KEY_HRP, [1u8; 31].to_base32(), Variant::Bech32).unwrap();
+        assert!(parse_public_key(&short).is_err());
+        let bech32m =
+            bech32::encode(PUBLIC_KEY_HRP, [1u8; 32].to_base32(), Variant::Bech32m).unwrap();
+        assert!(parse_public_key(&bech32m).is_err());
+    }
+}
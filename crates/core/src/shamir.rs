@@ -0,0 +1,232 @@
+//! Shamir secret sharing of a trash data key over GF(256), so that any `threshold`
+//! of the shares reconstruct the key and fewer reveal nothing about it.
+
+use crate::{
+    error::{Error, Result},
+    utils,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+
+const SHARE_PREFIX: &str = "tsbin-share-1";
+
+/// One share of a split key: the evaluation point and the polynomial values at it.
+pub struct Share {
+    pub threshold: u8,
+    pub x: u8,
+    pub y: [u8; 32],
+}
+
+impl Share {
+    /// Printable form: `tsbin-share-1-<threshold>-<x>-<base64url y>`.
+    pub fn encode(&self) -> String {
+        format!(
+            "{}-{}-{}-{}",
+            SHARE_PREFIX,
+            self.threshold,
+            self.x,
+            URL_SAFE_NO_PAD.encode(self.y)
+        )
+    }
+
+    pub fn decode(encoded: &str) -> Result<Self> {
+        let invalid = || Error::format("Invalid key share");
+        let rest = encoded
+            .trim()
+            .strip_prefix(SHARE_PREFIX)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .ok_or_else(invalid)?;
+        let mut parts = rest.splitn(3, '-');
+        let threshold = parts
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(invalid)?;
+        let x = parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(invalid)?;
+        let y = parts
+            .next()
+            .and_then(|y| URL_SAFE_NO_PAD.decode(y).ok())
+            .and_then(|y| y.try_into().ok())
+            .ok_or_else(invalid)?;
+        if x == 0 || threshold < 2 {
+            return Err(invalid());
+        }
+        Ok(Self { threshold, x, y })
+    }
+}
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = a >> 7;
+        a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(carry));
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse as a^254; zero has none and is never passed in.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+pub fn split(secret: &[u8; 32], threshold: u8, total: u8) -> Result<Vec<Share>> {
+    if threshold < 2 || threshold > total {
+        return Err(Error::invalid(
+            "Share threshold must be at least 2 and at most the number of shares",
+        ));
+    }
+
+    // One random polynomial per secret byte, with the byte as its constant term
+    let coefficients: Vec<[u8; 32]> = (1..threshold).map(|_| utils::random_bytes()).collect();
+    let shares = (1..=total)
+        .map(|x| {
+            let mut y = [0u8; 32];
+            for (i, byte) in y.iter_mut().enumerate() {
+                // Horner's rule from the highest coefficient down to the secret
+                let mut value = 0;
+                for coefficient in coefficients.iter().rev() {
+                    value = gf_mul(value, x) ^ coefficient[i];
+                }
+                *byte = gf_mul(value, x) ^ secret[i];
+            }
+            Share { threshold, x, y }
+        })
+        .collect();
+    Ok(shares)
+}
+
+pub fn combine(shares: &[Share]) -> Result<[u8; 32]> {
+    let threshold = shares.first().map_or(2, |share| share.threshold);
+    if shares.iter().any(|share| share.threshold != threshold) {
+        return Err(Error::invalid("Key shares belong to different splits"));
+    }
+    for (i, share) in shares.iter().enumerate() {
+        if shares[..i].iter().any(|other| other.x == share.x) {
+            return Err(Error::invalid("Duplicate key share"));
+        }
+    }
+    if shares.len() < threshold as usize {
+        return Err(Error::invalid(format!(
+            "{} key shares are needed, got {}",
+            threshold,
+            shares.len()
+        )));
+    }
+
+    // Lagrange interpolation at x = 0; subtraction is XOR in GF(256)
+    let mut secret = [0u8; 32];
+    for share in shares {
+        let mut basis = 1;
+        for other in shares.iter().filter(|other| other.x != share.x) {
+            basis = gf_mul(basis, gf_mul(other.x, gf_inv(other.x ^ share.x)));
+        }
+        for (byte, y) in secret.iter_mut().zip(share.y) {
+            *byte ^= gf_mul(basis, y);
+        }
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 32] = [9; 32];
+
+    /// Decodes the shares at `picks` of `encoded`, in that order.
+    fn shares(encoded: &[String], picks: &[usize]) -> Vec<Share> {
+        picks
+            .iter()
+            .map(|&i| Share::decode(&encoded[i]).unwrap())
+            .collect()
+    }
+
+    /// A fresh 3-of-5 split of `SECRET`, encoded.
+    fn split_encoded() -> Vec<String> {
+        split(&SECRET, 3, 5)
+            .unwrap()
+            .iter()
+            .map(Share::encode)
+            .collect()
+    }
+
+    #[test]
+    fn any_threshold_shares_recover_the_secret() {
+        let encoded = split_encoded();
+        for picks in [[0, 1, 2], [2, 4, 3], [4, 0, 2], [1, 3, 0]] {
+            assert_eq!(combine(&shares(&encoded, &picks)).unwrap(), SECRET);
+        }
+        assert_eq!(
+            combine(&shares(&encoded, &[4, 3, 2, 1, 0])).unwrap(),
+            SECRET
+        );
+    }
+
+    #[test]
+    fn too_few_or_duplicate_shares_are_rejected() {
+        let encoded = split_encoded();
+        assert_eq!(
+            combine(&shares(&encoded, &[0, 1])).unwrap_err(),
+            Error::invalid("3 key shares are needed, got 2")
+        );
+        assert_eq!(
+            combine(&shares(&encoded, &[0, 1, 1])).unwrap_err(),
+            Error::invalid("Duplicate key share")
+        );
+        assert!(combine(&[]).is_err());
+        assert!(split(&SECRET, 1, 5).is_err());
+        assert!(split(&SECRET, 4, 3).is_err());
+    }
+
+    #[test]
+    fn shares_round_trip_through_their_encoding() {
+        let share = Share {
+            threshold: 3,
+            x: 200,
+            y: [0xfb; 32],
+        };
+        let encoded = share.encode();
+        assert_eq!(
+            encoded,
+            format!("tsbin-share-1-3-200-{}", URL_SAFE_NO_PAD.encode([0xfb; 32]))
+        );
+        let decoded = Share::decode(&format!(" {}\n", encoded)).unwrap();
+        assert_eq!(
+            (decoded.threshold, decoded.x, decoded.y),
+            (share.threshold, share.x, share.y)
+        );
+    }
+
+    #[test]
+    fn malformed_shares_are_rejected() {
+        let y = URL_SAFE_NO_PAD.encode([1; 32]);
+        for encoded in [
+            format!("tsbin-share-2-3-1-{}", y),
+            format!("tsbin-share-13-1-{}", y),
+            format!("tsbin-share-1-3-{}", y),
+            format!("tsbin-share-1-x-1-{}", y),
+            format!("tsbin-share-1-3-256-{}", y),
+            format!("tsbin-share-1-3-0-{}", y),
+            format!("tsbin-share-1-1-1-{}", y),
+            format!("tsbin-share-1-3-1-{}", URL_SAFE_NO_PAD.encode([1; 31])),
+            format!("tsbin-share-1-3-1-{}=", y),
+            "tsbin-share-1-3-1-!!!!".to_string(),
+            String::new(),
+        ] {
+            assert!(Share::decode(&encoded).is_err(), "{}", encoded);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    error::{Error, Result},
+    manifest::Manifest,
+};
+use base64::{
+    Engine,
+    engine::general_purpose::{self, STANDARD_NO_PAD},
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A creator signature stored in the trash metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashSignature {
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// The fingerprint shown for a signer, in the `SHA256:...` form ssh uses.
+pub fn fingerprint(key: &VerifyingKey) -> String {
+    format!(
+        "SHA256:{}",
+        STANDARD_NO_PAD.encode(Sha256::digest(key.as_bytes()))
+    )
+}
+
+fn decode_32(value: &str) -> Result<[u8; 32]> {
+    general_purpose::STANDARD
+        .decode(value.trim())?
+        .try_into()
+        .map_err(|_| Error::format("Invalid key length"))
+}
+
+pub fn parse_signing_key(secret_key: &str) -> Result<SigningKey> {
+    decode_32(secret_key).map(|bytes| SigningKey::from_bytes(&bytes))
+}
+
+pub fn parse_verifying_key(public_key: &str) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&decode_32(public_key)?)
+        .map_err(|_| Error::format("Invalid signing public key"))
+}
+
+pub fn sign(manifest: &Manifest, key: &SigningKey) -> TrashSignature {
+    let signature = key.sign(&manifest.to_bytes());
+    TrashSignature {
+        public_key: general_purpose::STANDARD.encode(key.verifying_key().as_bytes()),
+        signature: general_purpose::STANDARD.encode(signature.to_bytes()),
+    }
+}
+
+/// Verifies the signature stored in the metadata, if there is one, and returns the
+/// signer's fingerprint. A trash with a signature that does not verify is an error.
+pub fn verify(
+    metadata: Option<&serde_json::Value>,
+    chunk_hashes: Vec<String>,
+) -> Result<Option<String>> {
+    let Some(metadata) = metadata.filter(|metadata| !metadata["signature"].is_null()) else {
+        return Ok(None);
+    };
+    let signature: TrashSignature = serde_json::from_value(metadata["signature"].clone())?;
+
+    let public_key = parse_verifying_key(&signature.public_key)?;
+    let signature_bytes: [u8; 64] = general_purpose::STANDARD
+        .decode(&signature.signature)?
+        .try_into()
+        .map_err(|_| Error::format("Invalid signature"))?;
+
+    let manifest = Manifest::new(metadata, chunk_hashes);
+    public_key
+        .verify_strict(
+            &manifest.to_bytes(),
+            &Signature::from_bytes(&signature_bytes),
+        )
+        .map_err(|_| Error::crypto("Trash signature does not match its content"))?;
+
+    Ok(Some(fingerprint(&public_key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn signed_metadata(key: &SigningKey, chunk_hashes: &[String]) -> serde_json::Value {
+        let mut metadata = json!({
+            "encryption_type": "aes256gcm",
+            "filename": "notes.txt",
+            "total_chunks": 2,
+        });
+        let manifest = Manifest::new(&metadata, chunk_hashes.to_vec());
+        metadata["signature"] = json!(sign(&manifest, key));
+        metadata
+    }
+
+    fn chunk_hashes() -> Vec<String> {
+        vec![
+            Manifest::chunk_hash(b"first"),
+            Manifest::chunk_hash(b"second"),
+        ]
+    }
+
+    #[test]
+    fn signature_reports_the_signer_fingerprint() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let metadata = signed_metadata(&key, &chunk_hashes());
+        assert_eq!(
+            verify(Some(&metadata), chunk_hashes()).unwrap(),
+            Some(fingerprint(&key.verifying_key()))
+        );
+        assert!(verify(Some(&json!({})), chunk_hashes()).unwrap().is_none());
+    }
+
+    #[test]
+    fn modified_manifest_fails_verification() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let metadata = signed_metadata(&key, &chunk_hashes());
+        let mismatch = Error::crypto("Trash signature does not match its content");
+
+        let mut renamed = metadata.clone();
+        renamed["filename"] = json!("invoice.pdf");
+        assert_eq!(
+            verify(Some(&renamed), chunk_hashes()).unwrap_err(),
+            mismatch
+        );
+
+        let mut reordered = chunk_hashes();
+        reordered.reverse();
+        assert_eq!(verify(Some(&metadata), reordered).unwrap_err(), mismatch);
+        assert_eq!(
+            verify(Some(&metadata), chunk_hashes()[..1].to_vec()).unwrap_err(),
+            mismatch
+        );
+    }
+
+    #[test]
+    fn substituted_verify_key_fails_verification() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let other = SigningKey::from_bytes(&[2; 32]);
+        let mut metadata = signed_metadata(&key, &chunk_hashes());
+        metadata["signature"]["public_key"] =
+            json!(general_purpose::STANDARD.encode(other.verifying_key().as_bytes()));
+        assert_eq!(
+            verify(Some(&metadata), chunk_hashes()).unwrap_err(),
+            Error::crypto("Trash signature does not match its content")
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! HTTP as `TsbinClient` needs it. Every platform plugs in its own transport: fetch in
+//! browser windows and workers, and with the `native` feature a blocking native client.
+
+use crate::error::Result;
+
+/// A request body.
+pub enum Body {
+    Empty,
+    Json(serde_json::Value),
+    /// `multipart/form-data` fields, sent in order
+    Multipart(Vec<Part>),
+}
+
+/// A form field; fields with a file name are sent as binary file parts.
+pub struct Part {
+    pub name: &'static str,
+    pub file_name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+pub struct Request {
+    pub method: &'static str,
+    pub url: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Body,
+}
+
+/// A response of any status; `TsbinClient` decides which ones are errors.
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+// The futures are not `Send`: those of fetch cannot be
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    /// Sends the request, failing only when no response came back at all.
+    async fn send(&self, request: Request) -> Result<Response>;
+}
+
+#[cfg(feature = "native")]
+pub use native::NativeTransport;
+
+#[cfg(feature = "native")]
+mod native {
+    use super::{Body, Request, Response, Transport};
+    use crate::{
+        error::{Error, Result},
+        utils,
+    };
+    use std::io::Read;
+
+    /// A blocking transport for native tools. Its futures finish on their first poll.
+    pub struct NativeTransport {
+        agent: ureq::Agent,
+    }
+
+    impl NativeTransport {
+        pub fn new() -> Self {
+            Self {
+                agent: ureq::Agent::new(),
+            }
+        }
+    }
+
+    impl Default for NativeTransport {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Transport for NativeTransport {
+        async fn send(&self, request: Request) -> Result<Response> {
+            let mut builder = self.agent.request(request.method, &request.url);
+            for (name, value) in &request.headers {
+                builder = builder.set(name, value);
+            }
+            let response = match request.body {
+                Body::Empty => builder.call(),
+                Body::Json(body) => builder.send_json(body),
+                Body::Multipart(parts) => {
+                    let boundary = format!("tsbin-{}", utils::generate_uuid());
+                    builder
+                        .set(
+                            "Content-Type",
+                            &format!("multipart/form-data; boundary={}", boundary),
+                        )
+                        .send_bytes(&multipart(&boundary, &parts))
+                }
+            };
+            let response = match response {
+                Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+                Err(e) => return Err(Error::Transport(e.to_string())),
+            };
+
+            let status = response.status();
+            let mut body = Vec::new();
+            response
+                .into_reader()
+                .read_to_end(&mut body)
+                .map_err(|e| Error::Transport(e.to_string()))?;
+            Ok(Response { status, body })
+        }
+    }
+
+    fn multipart(boundary: &str, parts: &[super::Part]) -> Vec<u8> {
+        let mut body = Vec::with_capacity(parts.iter().map(|part| part.data.len() + 128).sum());
+        for part in parts {
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            match &part.file_name {
+                Some(file_name) => body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                        part.name, file_name
+                    )
+                    .as_bytes(),
+                ),
+                None => body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                        part.name
+                    )
+                    .as_bytes(),
+                ),
+            }
+            body.extend_from_slice(&part.data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        body
+    }
+}
@@ -0,0 +1,1047 @@
+//! Whole trashes: sealing content and metadata on the way up, opening and checking them on
+//! the way down, and managing their key slots. Every frontend goes through `Session`, so a
+//! trash created by one opens in all the others.
+
+use crate::{
+    Cipher, DerivedKey, Encryptor, Manifest, TsbinClient,
+    age::{self, AgeIdentity},
+    backend::CryptoBackend,
+    error::{Error, Result},
+    keywrap::{self, KeySlot},
+    manifest,
+    recipient::{self, RecipientSlot},
+    shamir::{self, Share},
+    signing,
+    transport::Transport,
+    types::{TrashAccess, UploadProgress},
+    utils,
+};
+use base64::{Engine, engine::general_purpose};
+use serde_json::json;
+use std::{cell::RefCell, collections::BTreeMap};
+use zeroize::Zeroizing;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 10 * 1024 * 1024;
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// How the caller proves access to a trash's data key.
+pub enum Unlock<'a> {
+    /// A passcode, together with the hash of the keyfile for trashes that require one
+    Passcode(&'a str, Option<[u8; 32]>),
+    /// An `AGE-SECRET-KEY-1...` secret key of one of the trash recipients
+    Identity(&'a str),
+    /// At least the threshold of the printable key shares handed out at creation
+    Shares(&'a [String]),
+    /// The caller-managed raw data key
+    Key(&'a [u8; 32]),
+}
+
+impl Unlock<'_> {
+    /// The passcode hash the server checks before handing out or deleting trashes.
+    pub fn passcode_hash(&self) -> String {
+        match self {
+            Unlock::Passcode(passcode, _) => utils::hash_passphrase(passcode),
+            Unlock::Identity(_) | Unlock::Shares(_) | Unlock::Key(_) => utils::hash_passphrase(""),
+        }
+    }
+
+    /// The age identity for trashes uploaded as age files.
+    fn age_identity(&self) -> Result<AgeIdentity<'_>> {
+        match self {
+            Unlock::Passcode(passcode, _) => Ok(AgeIdentity::Passphrase(passcode)),
+            Unlock::Identity(secret_key) => Ok(AgeIdentity::X25519(recipient::parse_secret_key(
+                secret_key,
+            )?)),
+            Unlock::Shares(_) | Unlock::Key(_) => Err(Error::invalid(
+                "age trashes can only be opened with a passphrase or age identity",
+            )),
+        }
+    }
+}
+
+/// Where the data key of a new trash comes from, besides the slots wrapping it.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum KeySource {
+    /// Freshly generated and only reachable through key slots and recipients
+    #[default]
+    Random,
+    /// Freshly generated and split into `total` shares, `threshold` of which open the trash
+    Shares(u8, u8),
+    /// Supplied by the caller, who keeps it
+    Raw,
+}
+
+/// How a new trash is sealed and uploaded.
+pub struct SealOptions<'a> {
+    pub cipher: Cipher,
+    pub expire_at: Option<&'a str>,
+    pub chunk_size: usize,
+    pub max_retries: u32,
+    /// The main passcode. When empty it gets no key slot if the trash is encrypted to
+    /// recipients, otherwise anyone could open it.
+    pub passcode: Option<&'a str>,
+    /// Extra passcodes that can open the trash, each stored in its own key slot
+    pub passcodes: &'a [String],
+    /// SHA-256 of the keyfile every passcode has to be combined with
+    pub keyfile_hash: Option<[u8; 32]>,
+    /// `age1...` public keys the trash is encrypted to, in addition to the passcodes
+    pub recipients: &'a [String],
+    /// Base64 Ed25519 secret key the trash is signed with
+    pub signing_key: Option<&'a str>,
+    pub key_source: KeySource,
+}
+
+impl Default for SealOptions<'_> {
+    fn default() -> Self {
+        Self {
+            cipher: Cipher::default(),
+            expire_at: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_retries: DEFAULT_MAX_RETRIES,
+            passcode: None,
+            passcodes: &[],
+            keyfile_hash: None,
+            recipients: &[],
+            signing_key: None,
+            key_source: KeySource::Random,
+        }
+    }
+}
+
+impl SealOptions<'_> {
+    fn main_passcode(&self) -> Option<&str> {
+        self.passcode
+            .filter(|passcode| !passcode.is_empty() || self.recipients.is_empty())
+    }
+}
+
+/// A chunk of plaintext, wherever the platform holds it.
+pub trait Plaintext {
+    fn size(&self) -> usize;
+
+    /// Copies the bytes starting at `offset` into `dst`, filling it.
+    fn copy_to(&self, offset: usize, dst: &mut [u8]);
+}
+
+impl Plaintext for Zeroizing<Vec<u8>> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn copy_to(&self, offset: usize, dst: &mut [u8]) {
+        dst.copy_from_slice(&self[offset..offset + dst.len()]);
+    }
+}
+
+/// Where the plaintext of a new file trash is read from, chunk by chunk.
+// The futures are not `Send`: those reading JS files cannot be
+#[allow(async_fn_in_trait)]
+pub trait ChunkSource {
+    type Chunk: Plaintext;
+
+    /// The total size, when it is known before reading.
+    fn size(&self) -> Option<usize>;
+
+    /// Reads up to `chunk_size` bytes; only the last chunk is shorter. `None` once the
+    /// source is exhausted.
+    async fn next_chunk(&mut self, chunk_size: usize) -> Result<Option<Self::Chunk>>;
+}
+
+/// A file about to be encrypted: its contents and what its trash records about it.
+pub struct NewFile<S> {
+    pub name: String,
+    pub mime_type: String,
+    pub source: S,
+}
+
+/// A decrypted text trash, with the signer fingerprint of signed ones.
+pub struct OpenedText {
+    pub id: String,
+    pub encryption_type: Option<String>,
+    pub text_length: usize,
+    pub content: Zeroizing<Vec<u8>>,
+    pub signer: Option<String>,
+}
+
+/// A decrypted file trash. Its name and type are the authenticated ones when the trash
+/// carries a manifest MAC.
+pub struct OpenedFile {
+    pub content: Zeroizing<Vec<u8>>,
+    pub file_name: String,
+    pub mime_type: String,
+    pub file_size: usize,
+    pub signer: Option<String>,
+}
+
+/// The Telegram references of an uploaded file, in chunk order.
+struct UploadedChunks {
+    file_ids: Vec<String>,
+    message_ids: Vec<u32>,
+    chunk_hashes: Vec<String>,
+    original_size: usize,
+}
+
+/// Records how a split data key was shared; the shares themselves never leave the creator.
+fn key_shares_metadata(key_source: KeySource) -> serde_json::Value {
+    match key_source {
+        KeySource::Shares(threshold, total) => json!({ "threshold": threshold, "total": total }),
+        KeySource::Random | KeySource::Raw => serde_json::Value::Null,
+    }
+}
+
+fn recipient_slots_for(data_key: &[u8; 32], options: &SealOptions) -> Result<Vec<RecipientSlot>> {
+    options
+        .recipients
+        .iter()
+        .map(|recipient| recipient::wrap_for_recipient(data_key, recipient))
+        .collect()
+}
+
+/// Signs the manifest of the metadata and ciphertext chunks when the options carry a signing key.
+fn sign_metadata(
+    metadata: &mut serde_json::Value,
+    chunk_hashes: Vec<String>,
+    options: &SealOptions,
+) -> Result<()> {
+    if let Some(signing_key) = options.signing_key {
+        let key = signing::parse_signing_key(signing_key)?;
+        let manifest = Manifest::new(metadata, chunk_hashes);
+        metadata["signature"] = json!(signing::sign(&manifest, &key));
+    }
+    Ok(())
+}
+
+/// Splits a freshly generated data key into `total` printable shares, `threshold` of which
+/// open the trash sealed with it.
+pub fn split_data_key(data_key: &[u8; 32], threshold: u8, total: u8) -> Result<Vec<String>> {
+    Ok(shamir::split(data_key, threshold, total)?
+        .iter()
+        .map(Share::encode)
+        .collect())
+}
+
+/// Checks that an authenticated manifest describes the chunks that were downloaded.
+fn check_manifest(
+    manifest: Option<&Manifest>,
+    total_chunks: usize,
+    original_size: usize,
+) -> Result<()> {
+    if let Some(manifest) = manifest
+        && (manifest.total_chunks != Some(total_chunks as u64)
+            || manifest.original_size != Some(original_size as u64))
+    {
+        return Err(Error::crypto("Trash metadata does not match its content"));
+    }
+    Ok(())
+}
+
+/// A tsbin client together with the crypto backend in use and the passcode keys derived
+/// for it, which seal and open key slots without running PBKDF2 again.
+pub struct Session<T, B> {
+    client: TsbinClient<T>,
+    backend: B,
+    derived_keys: RefCell<Vec<DerivedKey>>,
+}
+
+impl<T: Transport, B: CryptoBackend> Session<T, B> {
+    pub fn new(client: TsbinClient<T>, backend: B) -> Self {
+        Self {
+            client,
+            backend,
+            derived_keys: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn client(&self) -> &TsbinClient<T> {
+        &self.client
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Keeps a derived key so trashes sealed or opened with its passcode skip key
+    /// derivation. It stays cached until `clear_derived_keys`.
+    pub fn cache_derived_key(&self, key: DerivedKey) {
+        self.derived_keys.borrow_mut().push(key);
+    }
+
+    pub fn clear_derived_keys(&self) {
+        self.derived_keys.borrow_mut().clear();
+    }
+
+    /// The cached keys, copied out so no borrow is held across an await.
+    fn derived_keys(&self) -> Vec<DerivedKey> {
+        self.derived_keys.borrow().clone()
+    }
+
+    /// Builds the encryptor for a trash, opening its data key from the slots in the metadata.
+    /// Trashes created before key wrapping are encrypted with the passcode-derived key.
+    async fn encryptor(
+        &self,
+        unlock: &Unlock<'_>,
+        metadata: Option<&serde_json::Value>,
+    ) -> Result<Encryptor> {
+        let null = serde_json::Value::Null;
+        let metadata = metadata.unwrap_or(&null);
+        let slots = keywrap::key_slots(metadata)?;
+        let recipients = recipient::recipient_slots(metadata)?;
+
+        let data_key = Zeroizing::new(match unlock {
+            Unlock::Passcode(..) if slots.is_empty() && metadata["raw_key"] == true => {
+                return Err(Error::invalid("This trash can only be opened with its key"));
+            }
+            Unlock::Passcode(passcode, _) if slots.is_empty() && recipients.is_empty() => {
+                let key = utils::derive_key_with(&self.backend, passcode, utils::LEGACY_SALT, None)
+                    .await?;
+                return Ok(Encryptor::from_key(*key));
+            }
+            Unlock::Passcode(passcode, keyfile_hash) => {
+                let cached = self.derived_keys();
+                let cached = cached
+                    .iter()
+                    .find(|kek| kek.matches(passcode, keyfile_hash.as_ref()));
+                let opened = match cached {
+                    Some(kek) => keywrap::open_key_slots_with(&self.backend, &slots, kek)
+                        .await
+                        .ok(),
+                    None => None,
+                };
+                match opened {
+                    Some((_, key)) => key,
+                    None => {
+                        keywrap::open_key_slots(
+                            &self.backend,
+                            &slots,
+                            passcode,
+                            keyfile_hash.as_ref(),
+                        )
+                        .await?
+                        .1
+                    }
+                }
+            }
+            Unlock::Identity(secret_key) => {
+                recipient::open_recipient_slots(&recipients, secret_key)?
+            }
+            Unlock::Shares(shares) => {
+                let shares = shares
+                    .iter()
+                    .map(|share| Share::decode(share))
+                    .collect::<Result<Vec<_>>>()?;
+                shamir::combine(&shares)?
+            }
+            Unlock::Key(key) => **key,
+        });
+        let cipher = match metadata["encryption_type"].as_str() {
+            Some(encryption_type) => Cipher::parse(encryption_type)?,
+            None => Cipher::default(),
+        };
+        // Only the legacy passcode-derived format above predates key commitments; trusting
+        // the server's `key_commitment` flag would let it strip the commitment
+        Ok(Encryptor::from_key(*data_key)
+            .with_cipher(cipher)
+            .require_commitment())
+    }
+
+    /// Wraps the data key once for the main passcode and once for every extra passcode,
+    /// and returns the slots along with the passcode hash of each, by slot id. Passcodes
+    /// with a cached derived key are wrapped without deriving again.
+    async fn key_slots(
+        &self,
+        data_key: &[u8; 32],
+        options: &SealOptions<'_>,
+    ) -> Result<(Vec<KeySlot>, BTreeMap<String, String>)> {
+        let mut passcodes: Vec<&str> = options.main_passcode().into_iter().collect();
+        for extra in options.passcodes {
+            if !passcodes.contains(&extra.as_str()) {
+                passcodes.push(extra);
+            }
+        }
+        let derived_keys = self.derived_keys();
+        let keyfile_hash = options.keyfile_hash.as_ref();
+        let mut slots = Vec::with_capacity(passcodes.len());
+        let mut passcode_hashes = BTreeMap::new();
+        for passcode in passcodes {
+            let slot = match derived_keys
+                .iter()
+                .find(|kek| kek.matches(passcode, keyfile_hash))
+            {
+                Some(kek) => keywrap::wrap_key_with(&self.backend, data_key, kek).await?,
+                None => keywrap::wrap_key(&self.backend, data_key, passcode, keyfile_hash).await?,
+            };
+            passcode_hashes.insert(slot.id.clone(), utils::hash_passphrase(passcode));
+            slots.push(slot);
+        }
+        Ok((slots, passcode_hashes))
+    }
+
+    /// The encryption metadata shared by text and file trashes, and the passcode hashes
+    /// the server checks before handing it out.
+    async fn sealed_metadata(
+        &self,
+        data_key: &[u8; 32],
+        options: &SealOptions<'_>,
+    ) -> Result<(serde_json::Value, TrashAccess)> {
+        let (key_slots, slot_passcode_hashes) = self.key_slots(data_key, options).await?;
+        let metadata = json!({
+            "expire_at": options.expire_at,
+            "encryption_type": options.cipher.as_str(),
+            "key_slots": key_slots,
+            "keyfile_required": options.keyfile_hash.is_some(),
+            "recipients": recipient_slots_for(data_key, options)?,
+            "key_shares": key_shares_metadata(options.key_source),
+            "raw_key": matches!(options.key_source, KeySource::Raw),
+            "key_commitment": true,
+        });
+        let access = TrashAccess {
+            passcode_hash: utils::hash_passphrase(options.main_passcode().unwrap_or_default()),
+            slot_passcode_hashes,
+            owner_token: Some(utils::owner_token(data_key)),
+        };
+        Ok((metadata, access))
+    }
+
+    /// Encrypts `content` under `data_key` and uploads it as a text trash. Returns the
+    /// trash id.
+    pub async fn put_text(
+        &self,
+        content: &str,
+        data_key: &[u8; 32],
+        options: &SealOptions<'_>,
+    ) -> Result<String> {
+        let content = content.as_bytes();
+        let encryptor = Encryptor::from_key(*data_key).with_cipher(options.cipher);
+        let encrypted_data = encryptor
+            .encrypt_with(&self.backend, content.len(), |offset, dst| {
+                dst.copy_from_slice(&content[offset..offset + dst.len()])
+            })
+            .await?;
+        let encrypted_text = general_purpose::STANDARD.encode(&encrypted_data);
+
+        let (mut metadata, access) = self.sealed_metadata(data_key, options).await?;
+        metadata["original_length"] = json!(content.len());
+        let chunk_hashes = vec![Manifest::chunk_hash(&encrypted_data)];
+        manifest::authenticate(&mut metadata, &chunk_hashes, data_key);
+        sign_metadata(&mut metadata, chunk_hashes, options)?;
+
+        self.client
+            .upload_text(&encrypted_text, &metadata, &access)
+            .await
+    }
+
+    /// Encrypts `file` chunk by chunk under `data_key` and uploads it as a file trash,
+    /// calling `on_progress` after every chunk and once the trash exists. Returns the
+    /// trash id.
+    pub async fn put_file<S: ChunkSource>(
+        &self,
+        mut file: NewFile<S>,
+        data_key: &[u8; 32],
+        options: &SealOptions<'_>,
+        mut on_progress: impl FnMut(&UploadProgress) -> Result<()>,
+    ) -> Result<String> {
+        let encryptor = Encryptor::from_key(*data_key).with_cipher(options.cipher);
+        let mut progress = UploadProgress::default();
+        let chunks = self
+            .upload_chunks(
+                &mut file.source,
+                Some(&encryptor),
+                options,
+                &mut progress,
+                &mut on_progress,
+            )
+            .await?;
+
+        let (mut metadata, access) = self.sealed_metadata(data_key, options).await?;
+        metadata["original_size"] = json!(chunks.original_size);
+        metadata["total_chunks"] = json!(chunks.file_ids.len());
+        metadata["chunk_size"] = json!(options.chunk_size);
+        metadata["filename"] = json!(file.name);
+        metadata["mime_type"] = json!(file.mime_type);
+        manifest::authenticate(&mut metadata, &chunks.chunk_hashes, data_key);
+
+        self.create_file_trash(
+            chunks,
+            metadata,
+            &access,
+            options,
+            progress,
+            &mut on_progress,
+        )
+        .await
+    }
+
+    /// Uploads a file that is already encrypted with age as is. It opens with the age
+    /// passphrase or with an age secret key.
+    pub async fn put_age_file<S: ChunkSource>(
+        &self,
+        mut file: NewFile<S>,
+        options: &SealOptions<'_>,
+        mut on_progress: impl FnMut(&UploadProgress) -> Result<()>,
+    ) -> Result<String> {
+        let mut progress = UploadProgress::default();
+        let chunks = self
+            .upload_chunks(
+                &mut file.source,
+                None,
+                options,
+                &mut progress,
+                &mut on_progress,
+            )
+            .await?;
+
+        // The server never sees the age passphrase, so the trash is public on its side.
+        // Without a data key there is no owner token either: it can only expire
+        let access = TrashAccess {
+            passcode_hash: utils::hash_passphrase(""),
+            slot_passcode_hashes: BTreeMap::new(),
+            owner_token: None,
+        };
+        let metadata = json!({
+            "expire_at": options.expire_at,
+            "original_size": chunks.original_size,
+            "total_chunks": chunks.file_ids.len(),
+            "chunk_size": options.chunk_size,
+            "encryption_type": "age",
+            "filename": file.name,
+            "mime_type": file.mime_type,
+        });
+
+        self.create_file_trash(
+            chunks,
+            metadata,
+            &access,
+            options,
+            progress,
+            &mut on_progress,
+        )
+        .await
+    }
+
+    /// Reads `source` chunk by chunk, seals every chunk and uploads it with retries,
+    /// reporting progress after each one.
+    async fn upload_chunks<S: ChunkSource>(
+        &self,
+        source: &mut S,
+        encryptor: Option<&Encryptor>,
+        options: &SealOptions<'_>,
+        progress: &mut UploadProgress,
+        on_progress: &mut impl FnMut(&UploadProgress) -> Result<()>,
+    ) -> Result<UploadedChunks> {
+        // Streams are counted as they are read
+        let known_size = source.size();
+        progress.total_chunks =
+            known_size.map_or(0, |size| size.div_ceil(options.chunk_size)) as u32;
+
+        let mut file_ids = Vec::new();
+        let mut message_ids = Vec::new();
+        let mut chunk_hashes = Vec::new();
+        let mut original_size = 0;
+
+        let mut chunk_index = 0;
+        while let Some(chunk) = source.next_chunk(options.chunk_size).await? {
+            original_size += chunk.size();
+            if known_size.is_none() {
+                progress.total_chunks = chunk_index + 1;
+            }
+
+            let encrypted_chunk = match encryptor {
+                Some(encryptor) => {
+                    encryptor
+                        .for_chunk(chunk_index)
+                        .encrypt_with(&self.backend, chunk.size(), |offset, dst| {
+                            chunk.copy_to(offset, dst)
+                        })
+                        .await?
+                }
+                None => {
+                    let mut data = vec![0; chunk.size()];
+                    chunk.copy_to(0, &mut data);
+                    data
+                }
+            };
+            chunk_hashes.push(Manifest::chunk_hash(&encrypted_chunk));
+
+            let mut retries = 0;
+            let response = loop {
+                match self
+                    .client
+                    .upload_chunk(&encrypted_chunk, chunk_index)
+                    .await
+                {
+                    Ok(response) => break response,
+                    Err(e) => {
+                        retries += 1;
+                        if retries >= options.max_retries {
+                            progress.failed_chunks.push(chunk_index);
+                            on_progress(progress)?;
+                            return Err(e.context(format!(
+                                "Failed to upload chunk {} after {} retries",
+                                chunk_index, options.max_retries
+                            )));
+                        }
+                    }
+                }
+            };
+            file_ids.push(response.file_id);
+            message_ids.push(response.message_id);
+            progress.uploaded_chunks += 1;
+            chunk_index += 1;
+            on_progress(progress)?;
+        }
+
+        Ok(UploadedChunks {
+            file_ids,
+            message_ids,
+            chunk_hashes,
+            original_size,
+        })
+    }
+
+    async fn create_file_trash(
+        &self,
+        chunks: UploadedChunks,
+        mut metadata: serde_json::Value,
+        access: &TrashAccess,
+        options: &SealOptions<'_>,
+        mut progress: UploadProgress,
+        on_progress: &mut impl FnMut(&UploadProgress) -> Result<()>,
+    ) -> Result<String> {
+        sign_metadata(&mut metadata, chunks.chunk_hashes, options)?;
+        let trash_id = self
+            .client
+            .create_file_trash(chunks.file_ids, chunks.message_ids, &metadata, access)
+            .await?;
+
+        progress.completed = true;
+        progress.trash_id = Some(trash_id.clone());
+        on_progress(&progress)?;
+        Ok(trash_id)
+    }
+
+    /// Downloads and decrypts the text trash `object_id`, checking its signature and
+    /// manifest first.
+    pub async fn open_text(&self, object_id: &str, unlock: &Unlock<'_>) -> Result<OpenedText> {
+        let encrypted_obj = self
+            .client
+            .get_text_obj(object_id, &unlock.passcode_hash())
+            .await?;
+        let encrypted_data = general_purpose::STANDARD.decode(&encrypted_obj.enc_trash_text)?;
+
+        let metadata = encrypted_obj.encryption_metadata.as_ref();
+        let chunk_hashes = vec![Manifest::chunk_hash(&encrypted_data)];
+        let signer = signing::verify(metadata, chunk_hashes.clone())?;
+        let encryptor = self.encryptor(unlock, metadata).await?;
+        manifest::verify(
+            metadata,
+            &chunk_hashes,
+            encryptor.key(),
+            encryptor.requires_commitment(),
+        )?;
+        let content = encryptor
+            .decrypt_with(&self.backend, encrypted_data)
+            .await?;
+
+        Ok(OpenedText {
+            id: encrypted_obj.id,
+            encryption_type: encrypted_obj.encryption_type,
+            text_length: encrypted_obj.text_length,
+            content: Zeroizing::new(content),
+            signer,
+        })
+    }
+
+    /// Downloads and decrypts every chunk of the file trash `object_id`, calling
+    /// `on_progress` after each one, and checks the result against the signature and
+    /// manifest before handing it out.
+    pub async fn open_file(
+        &self,
+        object_id: &str,
+        unlock: &Unlock<'_>,
+        mut on_progress: impl FnMut(&UploadProgress) -> Result<()>,
+    ) -> Result<OpenedFile> {
+        let trash_meta = self.client.get_file_trash_meta(object_id).await?;
+        let file_ids = trash_meta
+            .file_ids
+            .ok_or_else(|| Error::format("No file IDs in trash"))?;
+        let total_chunks = file_ids.len();
+        let metadata = trash_meta.encryption_metadata.as_ref();
+
+        let mut progress = UploadProgress {
+            total_chunks: total_chunks as u32,
+            trash_id: Some(object_id.to_string()),
+            ..UploadProgress::default()
+        };
+
+        // age trashes are stored as uploaded and decrypted as a whole once every chunk is in
+        let is_age = metadata.is_some_and(|metadata| metadata["encryption_type"] == "age");
+        let encryptor = match is_age {
+            true => None,
+            false => Some(self.encryptor(unlock, metadata).await?),
+        };
+
+        let mut decrypted_chunks = Vec::with_capacity(total_chunks);
+        let mut chunk_hashes = Vec::with_capacity(total_chunks);
+        for (index, file_id) in file_ids.iter().enumerate() {
+            let encrypted_chunk = self
+                .client
+                .download_chunk(object_id, file_id)
+                .await
+                .map_err(|e| e.context(format!("Failed to download chunk {}", index)))?;
+            chunk_hashes.push(Manifest::chunk_hash(&encrypted_chunk));
+            let decrypted_chunk = match &encryptor {
+                Some(encryptor) => {
+                    encryptor
+                        .for_chunk(index as u32)
+                        .decrypt_with(&self.backend, encrypted_chunk)
+                        .await?
+                }
+                None => encrypted_chunk,
+            };
+            decrypted_chunks.push(Zeroizing::new(decrypted_chunk));
+            progress.uploaded_chunks += 1;
+            on_progress(&progress)?;
+        }
+
+        let signer = signing::verify(metadata, chunk_hashes.clone())?;
+        let manifest = match &encryptor {
+            Some(encryptor) => manifest::verify(
+                metadata,
+                &chunk_hashes,
+                encryptor.key(),
+                encryptor.requires_commitment(),
+            )?,
+            None => None,
+        };
+        let decrypted_size: usize = decrypted_chunks.iter().map(|chunk| chunk.len()).sum();
+        check_manifest(manifest.as_ref(), total_chunks, decrypted_size)?;
+
+        let mut content = Vec::with_capacity(decrypted_size);
+        for chunk in decrypted_chunks {
+            content.extend_from_slice(&chunk);
+        }
+        if is_age {
+            content = age::decrypt(&content, &unlock.age_identity()?)?;
+        }
+
+        progress.completed = true;
+        on_progress(&progress)?;
+
+        // Prefer the authenticated names over what the server reports
+        let (file_name, mime_type, file_size) = match manifest {
+            Some(manifest) => (
+                manifest.filename.unwrap_or(trash_meta.file_name),
+                manifest.mime_type.unwrap_or(trash_meta.mime_type),
+                content.len(),
+            ),
+            None => (
+                trash_meta.file_name,
+                trash_meta.mime_type,
+                trash_meta.file_size,
+            ),
+        };
+
+        Ok(OpenedFile {
+            content: Zeroizing::new(content),
+            file_name,
+            mime_type,
+            file_size,
+            signer,
+        })
+    }
+
+    /// Fetches the stored encryption metadata of a trash by its public id.
+    async fn stored_metadata(
+        &self,
+        trash_id: &str,
+        passcode_hash: &str,
+    ) -> Result<serde_json::Value> {
+        let trash = self.client.get_trash(trash_id).await?;
+        let object_id = trash
+            .object_id
+            .ok_or_else(|| Error::invalid("Trash has no content"))?;
+
+        let metadata = match trash.trash_type.as_str() {
+            "TEXT" => self
+                .client
+                .get_text_obj(&object_id, passcode_hash)
+                .await?
+                .encryption_metadata
+                .unwrap_or_default(),
+            "FILE" => self
+                .client
+                .get_file_trash_meta(&object_id)
+                .await?
+                .encryption_metadata
+                .unwrap_or_default(),
+            _ => return Err(Error::format("Unknown trash type")),
+        };
+        Ok(metadata)
+    }
+
+    /// Fetches the stored encryption metadata of a trash that has key slots.
+    async fn encryption_metadata(
+        &self,
+        trash_id: &str,
+        passcode_hash: &str,
+    ) -> Result<serde_json::Value> {
+        let metadata = self.stored_metadata(trash_id, passcode_hash).await?;
+        if keywrap::key_slots(&metadata)?.is_empty() {
+            return Err(Error::invalid("Trash was created without key wrapping"));
+        }
+        Ok(metadata)
+    }
+
+    /// Deletes the trash `trash_id`. The server asks for the owner token, which comes
+    /// from the data key, so `unlock` has to open the trash.
+    pub async fn delete(&self, trash_id: &str, unlock: &Unlock<'_>) -> Result<()> {
+        let passcode_hash = unlock.passcode_hash();
+        let metadata = self.stored_metadata(trash_id, &passcode_hash).await?;
+        let owner_token = match metadata["encryption_type"] == "age" {
+            true => None,
+            false => Some(utils::owner_token(
+                self.encryptor(unlock, Some(&metadata)).await?.key(),
+            )),
+        };
+        self.client
+            .delete_trash(trash_id, owner_token.as_deref(), &passcode_hash)
+            .await
+    }
+
+    /// Stores new key slots along with the passcode hashes of those that are new or
+    /// changed; the server keeps the hashes of the others and checks the first slot's
+    /// passcode as the main one.
+    async fn update_key_slots(
+        &self,
+        trash_id: &str,
+        data_key: &[u8; 32],
+        passcode_hash: &str,
+        slots: Vec<KeySlot>,
+        slot_passcode_hashes: BTreeMap<String, String>,
+    ) -> Result<()> {
+        self.client
+            .update_encryption_metadata(
+                trash_id,
+                Some(&utils::owner_token(data_key)),
+                passcode_hash,
+                &json!({ "key_slots": slots }),
+                &slot_passcode_hashes,
+            )
+            .await
+    }
+
+    /// Re-wraps the trash data key under a new passcode without touching the uploaded
+    /// content. Only the key slot opened by `old_passcode` is replaced.
+    pub async fn change_passcode(
+        &self,
+        trash_id: &str,
+        old_passcode: &str,
+        new_passcode: &str,
+        keyfile_hash: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let old_hash = utils::hash_passphrase(old_passcode);
+        let metadata = self.encryption_metadata(trash_id, &old_hash).await?;
+        let mut slots = keywrap::key_slots(&metadata)?;
+        let (index, data_key) =
+            keywrap::open_key_slots(&self.backend, &slots, old_passcode, keyfile_hash.as_ref())
+                .await?;
+        let data_key = Zeroizing::new(data_key);
+
+        let keyfile_hash = keyfile_hash.filter(|_| slots[index].keyfile);
+        let mut slot = keywrap::wrap_key(
+            &self.backend,
+            &data_key,
+            new_passcode,
+            keyfile_hash.as_ref(),
+        )
+        .await?;
+        slot.id = slots[index].id.clone();
+        let slot_passcode_hashes =
+            BTreeMap::from([(slot.id.clone(), utils::hash_passphrase(new_passcode))]);
+        slots[index] = slot;
+
+        self.update_key_slots(trash_id, &data_key, &old_hash, slots, slot_passcode_hashes)
+            .await
+    }
+
+    /// Adds a key slot so `new_passcode` also opens the trash. Returns the new slot id.
+    pub async fn add_key_slot(
+        &self,
+        trash_id: &str,
+        passcode: &str,
+        new_passcode: &str,
+        keyfile_hash: Option<[u8; 32]>,
+    ) -> Result<String> {
+        let passcode_hash = utils::hash_passphrase(passcode);
+        let metadata = self.encryption_metadata(trash_id, &passcode_hash).await?;
+        let mut slots = keywrap::key_slots(&metadata)?;
+        let (index, data_key) =
+            keywrap::open_key_slots(&self.backend, &slots, passcode, keyfile_hash.as_ref()).await?;
+        let data_key = Zeroizing::new(data_key);
+
+        // The new passcode needs the same keyfile as the one that authorised it
+        let keyfile_hash = keyfile_hash.filter(|_| slots[index].keyfile);
+        let slot = keywrap::wrap_key(
+            &self.backend,
+            &data_key,
+            new_passcode,
+            keyfile_hash.as_ref(),
+        )
+        .await?;
+        let slot_id = slot.id.clone();
+        slots.push(slot);
+
+        let slot_passcode_hashes =
+            BTreeMap::from([(slot_id.clone(), utils::hash_passphrase(new_passcode))]);
+        self.update_key_slots(
+            trash_id,
+            &data_key,
+            &passcode_hash,
+            slots,
+            slot_passcode_hashes,
+        )
+        .await?;
+        Ok(slot_id)
+    }
+
+    /// Revokes the key slot `slot_id`; the passcode it belonged to no longer opens the
+    /// trash. Any remaining passcode can authorise the removal, but the last slot cannot
+    /// be removed.
+    pub async fn remove_key_slot(
+        &self,
+        trash_id: &str,
+        passcode: &str,
+        slot_id: &str,
+        keyfile_hash: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let passcode_hash = utils::hash_passphrase(passcode);
+        let metadata = self.encryption_metadata(trash_id, &passcode_hash).await?;
+        let mut slots = keywrap::key_slots(&metadata)?;
+        let (_, data_key) =
+            keywrap::open_key_slots(&self.backend, &slots, passcode, keyfile_hash.as_ref()).await?;
+        let data_key = Zeroizing::new(data_key);
+
+        let index = slots
+            .iter()
+            .position(|slot| slot.id == slot_id)
+            .ok_or_else(|| Error::invalid("Key slot not found"))?;
+        if slots.len() == 1 {
+            return Err(Error::invalid("Cannot remove the last key slot"));
+        }
+        slots.remove(index);
+
+        self.update_key_slots(trash_id, &data_key, &passcode_hash, slots, BTreeMap::new())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::RustCrypto,
+        mock::fixtures::{object_id, options, session},
+    };
+    use futures::executor::block_on;
+
+    #[test]
+    fn text_round_trip_through_key_slots() {
+        let session = session(RustCrypto);
+        let options = SealOptions {
+            passcode: Some("pw"),
+            ..options(0)
+        };
+        let data_key = keywrap::generate_data_key();
+        let slug = block_on(session.put_text("hello", &data_key, &options)).unwrap();
+        let object_id = block_on(object_id(session.client(), &slug));
+
+        let opened =
+            block_on(session.open_text(&object_id, &Unlock::Passcode("pw", None))).unwrap();
+        assert_eq!(&opened.content[..], b"hello");
+        assert!(block_on(session.open_text(&object_id, &Unlock::Passcode("wrong", None))).is_err());
+    }
+
+    #[test]
+    fn key_slots_are_added_and_removed() {
+        let session = session(RustCrypto);
+        let options = SealOptions {
+            passcode: Some("pw"),
+            ..options(0)
+        };
+        let data_key = keywrap::generate_data_key();
+        let slug = block_on(session.put_text("hello", &data_key, &options)).unwrap();
+        let object_id = block_on(object_id(session.client(), &slug));
+        let opens = |passcode| {
+            block_on(session.open_text(&object_id, &Unlock::Passcode(passcode, None))).is_ok()
+        };
+        let first_id = session.client().transport().metadata(&slug).unwrap()["key_slots"][0]["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let second_id = block_on(session.add_key_slot(&slug, "pw", "second", None)).unwrap();
+        assert!(opens("pw"));
+        assert!(opens("second"));
+
+        // Either passcode may revoke the other
+        block_on(session.remove_key_slot(&slug, "second", &first_id, None)).unwrap();
+        assert!(!opens("pw"));
+        assert!(opens("second"));
+
+        assert_eq!(
+            block_on(session.remove_key_slot(&slug, "second", &second_id, None)).unwrap_err(),
+            Error::invalid("Cannot remove the last key slot")
+        );
+        assert!(block_on(session.remove_key_slot(&slug, "second", "missing", None)).is_err());
+        assert!(opens("second"));
+    }
+
+    #[test]
+    fn signed_trash_reports_its_signer() {
+        let session = session(RustCrypto);
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[1; 32]);
+        let encoded = general_purpose::STANDARD.encode(signing_key.to_bytes());
+        let options = SealOptions {
+            passcode: Some("pw"),
+            signing_key: Some(&encoded),
+            ..options(0)
+        };
+        let data_key = keywrap::generate_data_key();
+        let slug = block_on(session.put_text("hello", &data_key, &options)).unwrap();
+        let object_id = block_on(object_id(session.client(), &slug));
+
+        let opened =
+            block_on(session.open_text(&object_id, &Unlock::Passcode("pw", None))).unwrap();
+        assert_eq!(
+            opened.signer,
+            Some(signing::fingerprint(&signing_key.verifying_key()))
+        );
+    }
+
+    #[test]
+    fn stripped_manifest_mac_is_rejected() {
+        let session = session(RustCrypto);
+        let key = [7; 32];
+        let options = SealOptions {
+            key_source: KeySource::Raw,
+            ..options(0)
+        };
+        let slug = block_on(session.put_text("hello", &key, &options)).unwrap();
+        let object_id = block_on(object_id(session.client(), &slug));
+
+        assert!(block_on(session.open_text(&object_id, &Unlock::Key(&key))).is_ok());
+
+        // Stripping the fields that mark the metadata as new must not make the MAC optional
+        let server = session.client().transport();
+        let mut metadata = server.metadata(&slug).unwrap();
+        for field in ["manifest_mac", "key_slots", "recipients", "key_commitment"] {
+            metadata.as_object_mut().unwrap().remove(field);
+        }
+        server.tamper_metadata(&slug, metadata);
+        let error = block_on(session.open_text(&object_id, &Unlock::Key(&key))).err();
+        assert_eq!(
+            error,
+            Some(Error::crypto("Trash metadata is missing its manifest MAC"))
+        );
+    }
+}
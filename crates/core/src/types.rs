@@ -0,0 +1,116 @@
+//! Request and response models of the tsbin API. With the `wasm` feature they are also
+//! exported to JavaScript as they are.
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// The envelope every endpoint answers with, the server's `Base`. Error bodies have the
+/// same shape with `success: false` and no data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiResponse<T> {
+    pub success: bool,
+    #[serde(default)]
+    pub message: Option<String>,
+    pub data: Option<T>,
+}
+
+impl<T> ApiResponse<T> {
+    /// The data of a successful response, or the error the API reported with `status`.
+    pub fn into_data(self, status: u16) -> Result<Option<T>> {
+        match self.success {
+            true => Ok(self.data),
+            false => Err(Error::api(status, self.message)),
+        }
+    }
+}
+
+/// What the server checks before handing out a trash. It is sent next to the encryption
+/// metadata when a trash is created, never inside it, and never served back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashAccess {
+    /// Hash of the main passcode, `0000` for trashes anyone with the id may fetch
+    pub passcode_hash: String,
+    /// Hash of the passcode of every key slot, by slot id
+    pub slot_passcode_hashes: BTreeMap<String, String>,
+    /// `utils::owner_token` of the data key; age trashes have none
+    pub owner_token: Option<String>,
+}
+
+/// What creating a text or file trash returns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreatedTrash {
+    pub trash_id: String,
+}
+
+/// A trash as looked up by its slug, before its content is fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trash {
+    pub id: String,
+    pub slug: String,
+    #[serde(rename = "type")]
+    pub trash_type: String, // "TEXT" | "FILE"
+    #[serde(default)]
+    pub object_id: Option<String>,
+    pub encrypted: bool,
+    #[serde(default)]
+    pub bad_report: u32,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub expire_at: Option<String>,
+}
+
+/// The stored form of a text trash: its base64 ciphertext and how it was encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedText {
+    pub id: String,
+    pub enc_trash_text: String,
+    #[serde(default)]
+    pub encryption_type: Option<String>,
+    pub text_length: usize,
+    #[serde(default)]
+    pub encryption_metadata: Option<serde_json::Value>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashMeta {
+    pub trash_id: String,
+    pub encrypted: bool,
+    pub trash_type: String, // "file" | "text"
+    pub expire_at: Option<String>,
+    pub file_ids: Option<Vec<String>>,
+    pub message_ids: Option<Vec<u64>>,
+    pub total_chunks: Option<u32>,
+    pub total_size: Option<u64>,
+
+    pub file_name: String,
+    pub mime_type: String,
+    pub file_size: usize,
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen(skip))]
+    #[serde(default)]
+    pub encryption_metadata: Option<serde_json::Value>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkUploadResponse {
+    pub file_id: String,
+    pub message_id: u32,
+    pub chunk_index: u32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UploadProgress {
+    pub total_chunks: u32,
+    pub uploaded_chunks: u32,
+    pub failed_chunks: Vec<u32>,
+    pub completed: bool,
+    pub trash_id: Option<String>,
+}
@@ -0,0 +1,79 @@
+use crate::{
+    backend::{self, CryptoBackend, RustCrypto},
+    error::Result,
+};
+use base64::{Engine, engine::general_purpose};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+pub const LEGACY_SALT: &[u8] = b"tsbin_salt_2024"; // Only kept for trashes created before key wrapping
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+pub fn derive_key(passphrase: &str, keyfile_hash: Option<&[u8; 32]>) -> [u8; 32] {
+    backend::run_sync(derive_key_with(
+        &RustCrypto,
+        passphrase,
+        LEGACY_SALT,
+        keyfile_hash,
+    ))
+    .map(|key| *key)
+    .expect("key derivation failure")
+}
+
+/// PBKDF2 over the passphrase, followed by the keyfile hash when the key needs both.
+pub async fn derive_key_with(
+    backend: &impl CryptoBackend,
+    passphrase: &str,
+    salt: &[u8],
+    keyfile_hash: Option<&[u8; 32]>,
+) -> Result<Zeroizing<[u8; 32]>> {
+    // Allocated at its final size so no partial copy of the passphrase is left behind
+    let mut password = Zeroizing::new(Vec::with_capacity(passphrase.len() + 32));
+    password.extend_from_slice(passphrase.as_bytes());
+    if let Some(keyfile_hash) = keyfile_hash {
+        password.extend_from_slice(keyfile_hash);
+    }
+    backend
+        .pbkdf2_sha256(&password, salt, PBKDF2_ITERATIONS)
+        .await
+        .map(Zeroizing::new)
+}
+
+pub fn hash_keyfile(keyfile: &[u8]) -> [u8; 32] {
+    Sha256::digest(keyfile).into()
+}
+
+pub fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).expect("failed to read random bytes");
+    bytes
+}
+
+pub fn hash_passphrase(passphrase: &str) -> String {
+    if passphrase.is_empty() {
+        return "0000".to_string();
+    }
+    if passphrase == "0000" {
+        return "0000".to_string();
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(passphrase.as_bytes());
+    let result = hasher.finalize();
+    general_purpose::STANDARD.encode(result)
+}
+
+/// The secret the server asks for before it deletes a trash or replaces its key slots.
+/// It comes from the data key, so whoever can open a trash can also manage it, and the
+/// server only keeps its hash.
+pub fn owner_token(data_key: &[u8]) -> String {
+    let mut token = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(None, data_key)
+        .expand(b"tsbin/owner-token/v1", &mut token[..])
+        .expect("valid hkdf length");
+    general_purpose::STANDARD.encode(&token[..])
+}
+
+pub fn generate_uuid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
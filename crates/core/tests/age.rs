@@ -0,0 +1,129 @@
+//! Checks that tsbin-core reads and writes the age format the way other age
+//! implementations do. The files in `vectors/age` were written by `generate.py`, an
+//! encoder built from the age spec that shares no code with this crate.
+//!
+//! The tests marked `#[ignore]` go through the reference `age` tool in both directions;
+//! run them with `cargo test -p tsbin-core --test age -- --ignored` where it is installed.
+
+use serde_json::Value;
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+use tsbin_core::{
+    age::{self, AgeIdentity, AgeRecipient},
+    recipient,
+};
+use x25519_dalek::PublicKey;
+
+fn index() -> Value {
+    serde_json::from_str(include_str!("vectors/age/index.json")).expect("valid index")
+}
+
+fn vector(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/vectors/age/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read(&path).expect(&path)
+}
+
+/// The plaintext every vector was sealed from: `len` bytes that do not line up with
+/// STREAM chunks.
+fn content(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn identity<'a>(index: &'a Value, unlock: &str) -> AgeIdentity<'a> {
+    match unlock {
+        "identity" => AgeIdentity::X25519(
+            recipient::parse_secret_key(index["identity"].as_str().unwrap()).unwrap(),
+        ),
+        "passphrase" => AgeIdentity::Passphrase(index["passphrase"].as_str().unwrap()),
+        other => panic!("unknown unlock {}", other),
+    }
+}
+
+#[test]
+fn keys_match_their_age_encoding() {
+    let index = index();
+    let secret = recipient::parse_secret_key(index["identity"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        recipient::encode_public_key(&PublicKey::from(&secret)),
+        index["recipient"]
+    );
+    assert_eq!(recipient::encode_secret_key(&secret), index["identity"]);
+}
+
+#[test]
+fn files_from_another_encoder_are_read() {
+    let index = index();
+    for case in index["files"].as_array().unwrap() {
+        let name = case["file"].as_str().unwrap();
+        let identity = identity(&index, case["unlock"].as_str().unwrap());
+        let opened = age::decrypt(&vector(name), &identity);
+        match case["error"].as_str() {
+            Some(error) => assert_eq!(opened.unwrap_err().to_string(), error, "{}", name),
+            None => {
+                let len = case["plaintext_len"].as_u64().unwrap() as usize;
+                assert_eq!(opened.unwrap(), content(len), "{}", name);
+            }
+        }
+    }
+}
+
+/// Runs the `age` tool with `input` on stdin and returns its stdout.
+fn run_age(args: &[&str], input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new("age")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("the age tool is installed");
+    // Written from another thread so a full stdout pipe cannot block the write
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(output.status.success(), "age {:?} failed", args);
+    output.stdout
+}
+
+#[test]
+#[ignore = "needs the age tool"]
+fn age_tool_reads_what_is_written() {
+    let index = index();
+    let secret = recipient::parse_secret_key(index["identity"].as_str().unwrap()).unwrap();
+    let identity_file = std::env::temp_dir().join(format!("tsbin-age-{}", std::process::id()));
+    std::fs::write(
+        &identity_file,
+        format!("{}\n", index["identity"].as_str().unwrap()),
+    )
+    .unwrap();
+
+    for len in [0, 5, 65536, 65537, 3 * 65536] {
+        let sealed = age::encrypt(
+            &content(len),
+            &[AgeRecipient::X25519(PublicKey::from(&secret))],
+        )
+        .unwrap();
+        let opened = run_age(
+            &["--decrypt", "-i", identity_file.to_str().unwrap()],
+            &sealed,
+        );
+        assert_eq!(opened, content(len), "{} bytes", len);
+    }
+    std::fs::remove_file(identity_file).unwrap();
+}
+
+#[test]
+#[ignore = "needs the age tool"]
+fn what_the_age_tool_writes_is_read() {
+    let index = index();
+    let identity = identity(&index, "identity");
+    for len in [0, 5, 65536, 65537, 3 * 65536] {
+        let sealed = run_age(
+            &["--encrypt", "-r", index["recipient"].as_str().unwrap()],
+            &content(len),
+        );
+        assert_eq!(age::decrypt(&sealed, &identity).unwrap(), content(len));
+    }
+}
@@ -0,0 +1,202 @@
+//! Checks tsbin-core against the known-answer vectors in `vectors/v2.json`. Other
+//! clients can run the same file to show they read and write the same trashes.
+//!
+//! The vectors are versioned: once published a file never changes, so an output that
+//! stops matching is a format break rather than a reason to regenerate them. `v1.json`
+//! stays as published; its key slots predate slot commitments and no longer open.
+
+use base64::{Engine, engine::general_purpose};
+use serde_json::Value;
+use tsbin_core::{
+    Cipher, Encryptor, KeySlot, Manifest,
+    backend::{RustCrypto, run_sync},
+    keywrap, legacy, manifest, utils,
+};
+
+fn vectors() -> Value {
+    serde_json::from_str(include_str!("vectors/v2.json")).expect("valid vectors")
+}
+
+fn cases(name: &str) -> Vec<Value> {
+    let vectors = vectors();
+    assert_eq!(vectors["version"], 2);
+    vectors[name].as_array().expect(name).clone()
+}
+
+fn bytes(value: &Value) -> Vec<u8> {
+    general_purpose::STANDARD
+        .decode(value.as_str().expect("base64 string"))
+        .expect("valid base64")
+}
+
+fn key(value: &Value) -> [u8; 32] {
+    bytes(value).try_into().expect("32-byte key")
+}
+
+fn keyfile_hash(value: &Value) -> Option<[u8; 32]> {
+    (!value.is_null()).then(|| key(value))
+}
+
+#[test]
+fn hash_passphrase() {
+    for case in cases("hash_passphrase") {
+        let passphrase = case["passphrase"].as_str().unwrap();
+        assert_eq!(
+            utils::hash_passphrase(passphrase),
+            case["hash"],
+            "{}",
+            passphrase
+        );
+    }
+}
+
+#[test]
+fn hash_keyfile() {
+    for case in cases("hash_keyfile") {
+        assert_eq!(
+            utils::hash_keyfile(&bytes(&case["keyfile"])).to_vec(),
+            bytes(&case["hash"])
+        );
+    }
+}
+
+#[test]
+fn derive_key() {
+    for case in cases("derive_key") {
+        assert_eq!(case["iterations"], 100_000);
+        let passphrase = case["passphrase"].as_str().unwrap();
+        let salt = bytes(&case["salt"]);
+        let keyfile_hash = keyfile_hash(&case["keyfile_hash"]);
+        let derived = run_sync(utils::derive_key_with(
+            &RustCrypto,
+            passphrase,
+            &salt,
+            keyfile_hash.as_ref(),
+        ))
+        .unwrap();
+        assert_eq!(*derived, key(&case["key"]), "{}", passphrase);
+
+        if salt == utils::LEGACY_SALT {
+            assert_eq!(
+                utils::derive_key(passphrase, keyfile_hash.as_ref()),
+                key(&case["key"])
+            );
+        }
+    }
+}
+
+#[test]
+fn key_slot() {
+    for case in cases("key_slot") {
+        let slot: KeySlot = serde_json::from_value(case["slot"].clone()).unwrap();
+        let passcode = case["passcode"].as_str().unwrap();
+        let keyfile_hash = keyfile_hash(&case["keyfile_hash"]);
+
+        let data_key = run_sync(keywrap::unwrap_key(
+            &RustCrypto,
+            &slot,
+            passcode,
+            keyfile_hash.as_ref(),
+        ))
+        .unwrap();
+        assert_eq!(data_key, key(&case["data_key"]));
+        assert!(
+            run_sync(keywrap::unwrap_key(
+                &RustCrypto,
+                &slot,
+                "wrong",
+                keyfile_hash.as_ref()
+            ))
+            .is_err()
+        );
+
+        let uncommitted = KeySlot {
+            commitment: String::new(),
+            ..slot
+        };
+        assert!(
+            run_sync(keywrap::unwrap_key(
+                &RustCrypto,
+                &uncommitted,
+                passcode,
+                keyfile_hash.as_ref()
+            ))
+            .is_err()
+        );
+    }
+}
+
+#[test]
+fn encrypt() {
+    for case in cases("encrypt") {
+        let encryptor = Encryptor::from_key(key(&case["key"]))
+            .with_cipher(Cipher::parse(case["cipher"].as_str().unwrap()).unwrap());
+        let plaintext = bytes(&case["plaintext"]);
+        let ciphertext = bytes(&case["ciphertext"]);
+        assert_eq!(encryptor.encrypt(&plaintext), ciphertext);
+        assert_eq!(
+            encryptor.require_commitment().decrypt(ciphertext).unwrap(),
+            plaintext
+        );
+    }
+}
+
+#[test]
+fn decrypt() {
+    for case in cases("decrypt") {
+        let encryptor = Encryptor::from_key(key(&case["key"]))
+            .with_cipher(Cipher::parse(case["cipher"].as_str().unwrap()).unwrap());
+        let encryptor = match case["commitment"] == true {
+            true => encryptor.require_commitment(),
+            false => encryptor,
+        };
+        assert_eq!(
+            encryptor.decrypt(bytes(&case["ciphertext"])).unwrap(),
+            bytes(&case["plaintext"])
+        );
+    }
+}
+
+#[test]
+fn chunk_hash() {
+    for case in cases("chunk_hash") {
+        assert_eq!(Manifest::chunk_hash(&bytes(&case["chunk"])), case["hash"]);
+    }
+}
+
+#[test]
+fn manifest_mac() {
+    for case in cases("manifest") {
+        let chunk_hashes: Vec<String> =
+            serde_json::from_value(case["chunk_hashes"].clone()).unwrap();
+        let data_key = key(&case["data_key"]);
+        let metadata = &case["metadata"];
+
+        let manifest = Manifest::new(metadata, chunk_hashes.clone());
+        assert_eq!(
+            String::from_utf8(manifest.to_bytes()).unwrap(),
+            case["manifest"]
+        );
+        assert_eq!(manifest.mac(&data_key), case["manifest_mac"]);
+        assert!(manifest::verify(Some(metadata), &chunk_hashes, &data_key, true).is_ok());
+
+        let mut unauthenticated = metadata.clone();
+        unauthenticated
+            .as_object_mut()
+            .unwrap()
+            .remove("manifest_mac");
+        manifest::authenticate(&mut unauthenticated, &chunk_hashes, &data_key);
+        assert_eq!(unauthenticated["manifest_mac"], case["manifest_mac"]);
+    }
+}
+
+#[test]
+fn legacy_file() {
+    for case in cases("legacy_file") {
+        let data = bytes(&case["data"]);
+        assert!(legacy::is_legacy(&data));
+        let file = legacy::decrypt(&data, case["passphrase"].as_str().unwrap()).unwrap();
+        assert_eq!(file.file_name, case["file_name"]);
+        assert_eq!(file.content, bytes(&case["content"]));
+    }
+}
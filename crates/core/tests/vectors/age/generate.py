@@ -0,0 +1,108 @@
+# An implementation of https://age-encryption.org/v1 independent of tsbin-core, written
+# from the spec to produce the known-answer files in this directory for tests/age.rs.
+# The files are random on every run; regenerating them is never needed to pass the tests.
+import base64, hashlib, hmac, json, os
+from cryptography.hazmat.primitives.asymmetric.x25519 import X25519PrivateKey
+from cryptography.hazmat.primitives.ciphers.aead import ChaCha20Poly1305
+from cryptography.hazmat.primitives.kdf.hkdf import HKDF
+from cryptography.hazmat.primitives import hashes, serialization
+
+OUT = os.path.dirname(os.path.abspath(__file__))
+raw = serialization.Encoding.Raw, serialization.PublicFormat.Raw
+
+def b64(b): return base64.b64encode(b).decode().rstrip("=")
+def hkdf(ikm, salt, info):
+    return HKDF(hashes.SHA256(), 32, salt, info).derive(ikm)
+
+CHARSET = "qpzry9x8gf2tvdw0s3jn54khce6mua7l"
+def polymod(values):
+    gen = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3]
+    chk = 1
+    for v in values:
+        b = chk >> 25
+        chk = (chk & 0x1ffffff) << 5 ^ v
+        for i in range(5):
+            chk ^= gen[i] if ((b >> i) & 1) else 0
+    return chk
+def bech32(hrp, data):
+    acc = bits = 0; five = []
+    for byte in data:
+        acc = (acc << 8) | byte; bits += 8
+        while bits >= 5:
+            bits -= 5; five.append((acc >> bits) & 31)
+    if bits: five.append((acc << (5 - bits)) & 31)
+    exp = [ord(c) >> 5 for c in hrp] + [0] + [ord(c) & 31 for c in hrp]
+    pm = polymod(exp + five + [0] * 6) ^ 1
+    chk = [(pm >> 5 * (5 - i)) & 31 for i in range(6)]
+    return hrp + "1" + "".join(CHARSET[d] for d in five + chk)
+
+def x25519_stanza(file_key, recipient_pub):
+    eph = X25519PrivateKey.generate()
+    eph_pub = eph.public_key().public_bytes(*raw)
+    from cryptography.hazmat.primitives.asymmetric.x25519 import X25519PublicKey
+    shared = eph.exchange(X25519PublicKey.from_public_bytes(recipient_pub))
+    key = hkdf(shared, eph_pub + recipient_pub, b"age-encryption.org/v1/X25519")
+    return ["X25519", b64(eph_pub)], ChaCha20Poly1305(key).encrypt(bytes(12), file_key, None)
+
+def scrypt_stanza(file_key, passphrase, work_factor):
+    salt = os.urandom(16)
+    key = hashlib.scrypt(passphrase.encode(), salt=b"age-encryption.org/v1/scrypt" + salt,
+                         n=2 ** work_factor, r=8, p=1, dklen=32)
+    return ["scrypt", b64(salt), str(work_factor)], ChaCha20Poly1305(key).encrypt(bytes(12), file_key, None)
+
+def seal(plaintext, stanzas, empty_last_chunk=False):
+    file_key = os.urandom(16)
+    header = b"age-encryption.org/v1\n"
+    for make in stanzas:
+        args, body = make(file_key)
+        header += ("-> " + " ".join(args) + "\n").encode()
+        body = b64(body)
+        lines = [body[i:i + 64] for i in range(0, len(body), 64)]
+        if len(body) % 64 == 0: lines.append("")
+        header += "".join(l + "\n" for l in lines).encode()
+    header += b"---"
+    mac = hmac.new(hkdf(file_key, b"", b"header"), header, hashlib.sha256).digest()
+    header += b" " + b64(mac).encode() + b"\n"
+    nonce = os.urandom(16)
+    aead = ChaCha20Poly1305(hkdf(file_key, nonce, b"payload"))
+    chunks = [plaintext[i:i + 65536] for i in range(0, len(plaintext), 65536)] or [b""]
+    if empty_last_chunk: chunks.append(b"")
+    out = header + nonce
+    for i, chunk in enumerate(chunks):
+        n = i.to_bytes(11, "big") + (b"\x01" if i + 1 == len(chunks) else b"\x00")
+        out += aead.encrypt(n, chunk, None)
+    return out
+
+def content(n): return bytes(i % 251 for i in range(n))
+
+secret = bytes(range(1, 33))
+identity = X25519PrivateKey.from_private_bytes(secret)
+public = identity.public_key().public_bytes(*raw)
+other = X25519PrivateKey.generate().public_key().public_bytes(*raw)
+passphrase = "correct horse battery staple"
+
+me = lambda fk: x25519_stanza(fk, public)
+them = lambda fk: x25519_stanza(fk, other)
+pw = lambda fk: scrypt_stanza(fk, passphrase, 10)
+cases = [
+    ("scrypt_empty.age", "passphrase", 0, [pw], False, None),
+    ("scrypt_one_chunk.age", "passphrase", 65536, [pw], False, None),
+    ("x25519_short.age", "identity", 5, [me], False, None),
+    ("x25519_two_chunks.age", "identity", 65537, [them, me], False, None),
+    ("x25519_three_chunks.age", "identity", 3 * 65536, [me], False, None),
+    ("x25519_empty_last_chunk.age", "identity", 65536, [me], True, "Corrupted age payload"),
+]
+index = {
+    "identity": bech32("age-secret-key-", secret).upper(),
+    "recipient": bech32("age", public),
+    "passphrase": passphrase,
+    "files": [],
+}
+for name, unlock, length, stanzas, empty_last, error in cases:
+    with open(f"{OUT}/{name}", "wb") as f:
+        f.write(seal(content(length), stanzas, empty_last))
+    case = {"file": name, "unlock": unlock, "plaintext_len": length}
+    if error: case["error"] = error
+    index["files"].append(case)
+with open(f"{OUT}/index.json", "w") as f:
+    json.dump(index, f, indent=2); f.write("\n")
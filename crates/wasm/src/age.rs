@@ -0,0 +1,42 @@
+use tsbin_core::{
+    Result,
+    age::{self, AgeIdentity, AgeRecipient},
+    recipient,
+};
+use wasm_bindgen::prelude::*;
+
+/// Encrypts `data` as an age file that `age --decrypt` opens with the passphrase.
+#[wasm_bindgen]
+pub fn age_encrypt_with_passphrase(
+    data: &[u8],
+    passphrase: &str,
+    work_factor: Option<u8>,
+) -> Result<Vec<u8>> {
+    let recipient = AgeRecipient::Scrypt {
+        passphrase: passphrase.to_string(),
+        work_factor: work_factor.unwrap_or(age::DEFAULT_WORK_FACTOR),
+    };
+    age::encrypt(data, &[recipient])
+}
+
+/// Encrypts `data` as an age file to `age1...` public keys.
+#[wasm_bindgen]
+pub fn age_encrypt_to_recipients(data: &[u8], recipients: Vec<String>) -> Result<Vec<u8>> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| recipient::parse_public_key(recipient).map(AgeRecipient::X25519))
+        .collect::<Result<Vec<_>>>()?;
+    age::encrypt(data, &recipients)
+}
+
+#[wasm_bindgen]
+pub fn age_decrypt_with_passphrase(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    age::decrypt(data, &AgeIdentity::Passphrase(passphrase))
+}
+
+/// Decrypts an age file with an `AGE-SECRET-KEY-1...` secret key.
+#[wasm_bindgen]
+pub fn age_decrypt_with_identity(data: &[u8], secret_key: &str) -> Result<Vec<u8>> {
+    let secret = recipient::parse_secret_key(secret_key)?;
+    age::decrypt(data, &AgeIdentity::X25519(secret))
+}
@@ -0,0 +1,165 @@
+//! SubtleCrypto as a `CryptoBackend`: it runs AES-GCM and PBKDF2 natively and off the
+//! wasm heap when the runtime has it, with tsbin-core's RustCrypto as the fallback.
+
+use crate::utils::js_error;
+use js_sys::{Array, Uint8Array};
+use tsbin_core::{
+    Error, Result,
+    backend::{CryptoBackend, RustCrypto},
+};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AesGcmParams, CryptoKey, Pbkdf2Params, SubtleCrypto};
+
+/// `crypto.subtle` of the current global scope, in windows, workers and Node alike.
+pub(crate) struct WebCrypto {
+    subtle: SubtleCrypto,
+}
+
+impl WebCrypto {
+    #[cfg(target_arch = "wasm32")]
+    fn detect() -> Option<Self> {
+        let crypto = js_sys::Reflect::get(&js_sys::global(), &"crypto".into()).ok()?;
+        let subtle = js_sys::Reflect::get(&crypto, &"subtle".into()).ok()?;
+        let subtle = subtle.dyn_into::<SubtleCrypto>().ok()?;
+        Some(Self { subtle })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn detect() -> Option<Self> {
+        None
+    }
+
+    async fn import_key(&self, key: &[u8], algorithm: &str, usage: &str) -> Result<CryptoKey> {
+        let usages = Array::of1(&usage.into());
+        let promise = self
+            .subtle
+            .import_key_with_str("raw", &Uint8Array::from(key), algorithm, false, &usages)
+            .map_err(js_error)?;
+        let key = JsFuture::from(promise).await.map_err(js_error)?;
+        Ok(key.unchecked_into())
+    }
+}
+
+impl CryptoBackend for WebCrypto {
+    async fn aes_gcm_seal(
+        &self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        let key = self.import_key(key, "AES-GCM", "encrypt").await?;
+        let params = AesGcmParams::new("AES-GCM", &Uint8Array::from(&nonce[..]));
+        let promise = self
+            .subtle
+            .encrypt_with_object_and_u8_array(&params, &key, plaintext)
+            .map_err(js_error)?;
+        let ciphertext = JsFuture::from(promise)
+            .await
+            .map_err(|_| Error::crypto("Encryption failed"))?;
+        Ok(Uint8Array::new(&ciphertext).to_vec())
+    }
+
+    async fn aes_gcm_open(
+        &self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>> {
+        let key = self.import_key(key, "AES-GCM", "decrypt").await?;
+        let params = AesGcmParams::new("AES-GCM", &Uint8Array::from(&nonce[..]));
+        let promise = self
+            .subtle
+            .decrypt_with_object_and_u8_array(&params, &key, ciphertext)
+            .map_err(js_error)?;
+        let plaintext = JsFuture::from(promise)
+            .await
+            .map_err(|_| Error::crypto("Decryption failed"))?;
+        Ok(Uint8Array::new(&plaintext).to_vec())
+    }
+
+    async fn pbkdf2_sha256(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+    ) -> Result<[u8; 32]> {
+        // Some engines refuse to import an empty PBKDF2 password
+        if password.is_empty() {
+            return RustCrypto.pbkdf2_sha256(password, salt, iterations).await;
+        }
+        let key = self.import_key(password, "PBKDF2", "deriveBits").await?;
+        let params = Pbkdf2Params::new(
+            "PBKDF2",
+            &"SHA-256".into(),
+            iterations,
+            &Uint8Array::from(salt),
+        );
+        let promise = self
+            .subtle
+            .derive_bits_with_object(&params, &key, 256)
+            .map_err(js_error)?;
+        let bits = JsFuture::from(promise).await.map_err(js_error)?;
+        Uint8Array::new(&bits)
+            .to_vec()
+            .try_into()
+            .map_err(|_| Error::Backend("Unexpected PBKDF2 output length".to_string()))
+    }
+}
+
+/// The backend in use, picked once per operation.
+pub(crate) enum Backend {
+    WebCrypto(WebCrypto),
+    RustCrypto(RustCrypto),
+}
+
+impl Backend {
+    pub(crate) fn detect() -> Self {
+        match WebCrypto::detect() {
+            Some(web_crypto) => Backend::WebCrypto(web_crypto),
+            None => Backend::RustCrypto(RustCrypto),
+        }
+    }
+}
+
+impl CryptoBackend for Backend {
+    async fn aes_gcm_seal(
+        &self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>> {
+        match self {
+            Backend::WebCrypto(backend) => backend.aes_gcm_seal(key, nonce, plaintext).await,
+            Backend::RustCrypto(backend) => backend.aes_gcm_seal(key, nonce, plaintext).await,
+        }
+    }
+
+    async fn aes_gcm_open(
+        &self,
+        key: &[u8; 32],
+        nonce: &[u8; 12],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>> {
+        match self {
+            Backend::WebCrypto(backend) => backend.aes_gcm_open(key, nonce, ciphertext).await,
+            Backend::RustCrypto(backend) => backend.aes_gcm_open(key, nonce, ciphertext).await,
+        }
+    }
+
+    async fn pbkdf2_sha256(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+    ) -> Result<[u8; 32]> {
+        match self {
+            Backend::WebCrypto(backend) => backend.pbkdf2_sha256(password, salt, iterations).await,
+            Backend::RustCrypto(backend) => backend.pbkdf2_sha256(password, salt, iterations).await,
+        }
+    }
+
+    fn works_in_place(&self) -> bool {
+        matches!(self, Backend::RustCrypto(_))
+    }
+}
@@ -1,24 +1,182 @@
-use crate::{client::TsbinClient, encryptor::Encryptor, types::*, utils};
-use base64::{Engine, engine::general_purpose};
+use crate::{
+    backend::Backend,
+    source::{self, ChunkSource},
+    transport::Fetch,
+    types::*,
+    utils::js_error,
+};
 use js_sys::Uint8Array;
-use serde_json::json;
-use std::collections::HashMap;
+use tsbin_core::{
+    Cipher, TsbinClient, age, keywrap, rawkey,
+    trash::{self, KeySource, NewFile, OpenedText, SealOptions, Session, Unlock},
+    utils,
+};
 use wasm_bindgen::prelude::*;
-use web_sys::{File, console};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::File;
+use zeroize::Zeroizing;
 
+/// The browser and Node.js face of `tsbin_core::trash::Session`: it converts arguments and
+/// results, reads JS files and streams, and forwards progress to JS callbacks.
 #[wasm_bindgen]
 pub struct TsbinController {
-    client: TsbinClient,
+    session: Session<Fetch, Backend>,
+}
+
+/// The seal options of `options`, with `passcode` as the main passcode.
+fn seal_options<'a>(
+    options: &'a EncryptionOptions,
+    passcode: Option<&'a str>,
+    key_source: KeySource,
+) -> Result<SealOptions<'a>, JsValue> {
+    Ok(SealOptions {
+        cipher: options
+            .encryption_type
+            .as_deref()
+            .map_or(Ok(Cipher::default()), Cipher::parse)?,
+        expire_at: options.expire_at.as_deref(),
+        chunk_size: options.chunk_size.unwrap_or(trash::DEFAULT_CHUNK_SIZE),
+        max_retries: options.max_retries.unwrap_or(trash::DEFAULT_MAX_RETRIES),
+        passcode,
+        passcodes: &options.passcodes,
+        keyfile_hash: options.keyfile_hash,
+        recipients: &options.recipients,
+        signing_key: options.signing_key.as_deref(),
+        key_source,
+    })
+}
+
+/// Forwards every progress update to `callback`, when there is one.
+fn report(
+    callback: &Option<js_sys::Function>,
+) -> impl FnMut(&UploadProgress) -> tsbin_core::Result<()> + '_ {
+    move |progress| {
+        if let Some(callback) = callback {
+            let progress =
+                serde_wasm_bindgen::to_value(progress).map_err(|e| js_error(e.into()))?;
+            callback
+                .call1(&JsValue::null(), &progress)
+                .map_err(js_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads and hashes the keyfile handed to a decrypt or key slot operation.
+async fn read_keyfile(keyfile: Option<File>) -> Result<Option<[u8; 32]>, JsValue> {
+    let Some(keyfile) = keyfile else {
+        return Ok(None);
+    };
+    let array_buffer = JsFuture::from(keyfile.array_buffer()).await?;
+    let keyfile = Zeroizing::new(Uint8Array::new(&array_buffer).to_vec());
+    Ok(Some(utils::hash_keyfile(&keyfile)))
+}
+
+fn text_content(mut opened: OpenedText) -> Result<TextTrashContent, JsValue> {
+    let text = String::from_utf8(std::mem::take(&mut *opened.content))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(TextTrashContent {
+        id: opened.id,
+        enc_trash_text: text,
+        encryption_type: opened.encryption_type.ok_or("Missing encryption type")?,
+        text_length: opened.text_length,
+        signer: opened.signer,
+    })
+}
+
+impl TsbinController {
+    async fn encrypt_text_with(
+        &self,
+        content: &str,
+        passcode: Option<&str>,
+        data_key: &[u8; 32],
+        key_source: KeySource,
+        options: &EncryptionOptions,
+    ) -> Result<String, JsValue> {
+        let options = seal_options(options, passcode, key_source)?;
+        Ok(self.session.put_text(content, data_key, &options).await?)
+    }
+
+    async fn encrypt_file_with(
+        &self,
+        file: NewFile<ChunkSource<'_>>,
+        passcode: Option<&str>,
+        data_key: &[u8; 32],
+        key_source: KeySource,
+        options: &EncryptionOptions,
+        progress_callback: &Option<js_sys::Function>,
+    ) -> Result<String, JsValue> {
+        let options = seal_options(options, passcode, key_source)?;
+        Ok(self
+            .session
+            .put_file(file, data_key, &options, report(progress_callback))
+            .await?)
+    }
+
+    async fn decrypt_text_with(
+        &self,
+        input: &str,
+        unlock: Unlock<'_>,
+    ) -> Result<TextTrashContent, JsValue> {
+        text_content(self.session.open_text(input, &unlock).await?)
+    }
+
+    async fn decrypt_file_with(
+        &self,
+        trash_id: String,
+        unlock: Unlock<'_>,
+        progress_callback: Option<js_sys::Function>,
+    ) -> Result<FileTrashContent, JsValue> {
+        let mut opened = self
+            .session
+            .open_file(&trash_id, &unlock, report(&progress_callback))
+            .await?;
+        Ok(FileTrashContent {
+            id: trash_id,
+            file: std::mem::take(&mut *opened.content),
+            mime_type: opened.mime_type,
+            file_name: opened.file_name,
+            file_size: opened.file_size,
+            signer: opened.signer,
+        })
+    }
 }
 
 #[wasm_bindgen]
 impl TsbinController {
+    /// `transport` picks where fetch comes from: `"window"`, `"worker"` inside Web Workers
+    /// and Service Workers, or `"global"` for `globalThis.fetch`. It is detected when omitted.
     #[wasm_bindgen(constructor)]
-    pub fn new(base_url: String, auth_token: String) -> Self {
-        let client = TsbinClient::new(base_url.clone(), auth_token.clone());
-        Self { client }
+    pub fn new(
+        base_url: String,
+        auth_token: String,
+        transport: Option<String>,
+    ) -> Result<TsbinController, JsValue> {
+        let transport = Fetch::named(transport.as_deref())?;
+        Ok(Self {
+            session: Session::new(
+                TsbinClient::new(base_url, auth_token, transport),
+                Backend::detect(),
+            ),
+        })
+    }
+
+    /// Keeps a key from `derive_passcode_key` so trashes sealed or opened with its passcode
+    /// skip key derivation. It stays cached until `clear_derived_keys`.
+    #[wasm_bindgen]
+    pub fn cache_derived_key(&self, key: crate::keywrap::DerivedKey) {
+        self.session.cache_derived_key(key.into_inner());
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_derived_keys(&self) {
+        self.session.clear_derived_keys();
     }
 
+    /// Wipes every cached derived key. The controller cannot be used afterwards.
+    #[wasm_bindgen]
+    pub fn dispose(self) {}
+
     #[wasm_bindgen]
     pub async fn encrypt_text(
         &self,
@@ -26,20 +184,45 @@ impl TsbinController {
         passcode: String,
         options: Option<EncryptionOptions>,
     ) -> Result<String, JsValue> {
-        let options = options.unwrap_or_else(|| EncryptionOptions::new());
-        let encryptor = Encryptor::new(passcode.clone());
-        let encrypted_data = encryptor.encrypt(content.as_bytes());
-        let encrypted_text = general_purpose::STANDARD.encode(&encrypted_data);
-
-        let metadata = json!({
-            "passcode_hash": utils::hash_passphrase(&passcode),
-            "expire_at": options.expire_at,
-            "original_length": content.len(),
-            "encryption_type": "aes256gcm"
-        });
+        let content = Zeroizing::new(content);
+        let passcode = Zeroizing::new(passcode);
+        let options = options.unwrap_or_default();
+        let data_key = Zeroizing::new(keywrap::generate_data_key());
+        self.encrypt_text_with(
+            &content,
+            Some(&passcode),
+            &data_key,
+            KeySource::Random,
+            &options,
+        )
+        .await
+    }
+
+    /// Creates a text trash without a passcode whose data key is split into `total` shares,
+    /// any `threshold` of which open it through `decrypt_text_with_shares`.
+    #[wasm_bindgen]
+    pub async fn encrypt_text_with_shares(
+        &self,
+        content: String,
+        threshold: u8,
+        total: u8,
+        options: Option<EncryptionOptions>,
+    ) -> Result<SharedTrash, JsValue> {
+        let content = Zeroizing::new(content);
+        let options = options.unwrap_or_default();
+        let data_key = Zeroizing::new(keywrap::generate_data_key());
+        let shares = trash::split_data_key(&data_key, threshold, total)?;
+        let trash_id = self
+            .encrypt_text_with(
+                &content,
+                None,
+                &data_key,
+                KeySource::Shares(threshold, total),
+                &options,
+            )
+            .await?;
 
-        let trash_id = self.client.upload_text(&encrypted_text, &metadata).await?;
-        Ok(trash_id)
+        Ok(SharedTrash { trash_id, shares })
     }
 
     #[wasm_bindgen]
@@ -47,40 +230,63 @@ impl TsbinController {
         &self,
         input: String,
         passcode: String,
+        keyfile: Option<File>,
     ) -> Result<TextTrashContent, JsValue> {
-        let passcode_hash = utils::hash_passphrase(&passcode);
-        let encrypted_obj = self.client.get_text_obj(&input, &passcode_hash).await?;
-
-        let encrypted_data = general_purpose::STANDARD
-            .decode(
-                encrypted_obj["enc_trash_text"]
-                    .as_str()
-                    .ok_or("Invalid JSON value")?,
-            )
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
-
-        let encryptor = Encryptor::new(passcode);
-        let decrypted_data = encryptor.decrypt(&encrypted_data);
-        let decrypted_text =
-            String::from_utf8(decrypted_data).map_err(|e| JsValue::from_str(&e.to_string()))?;
-
-        // stitch decrypted text and other obj fields and send
-        let result_obj = TextTrashContent {
-            id: encrypted_obj["id"]
-                .as_str()
-                .ok_or("Invalid JSON value")?
-                .to_string(),
-            enc_trash_text: decrypted_text,
-            encryption_type: encrypted_obj["encryption_type"]
-                .as_str()
-                .ok_or("Invalid JSON value")?
-                .to_string(),
-            text_length: encrypted_obj["text_length"]
-                .as_u64()
-                .ok_or("Invalid JSON value")? as usize,
-        };
+        let passcode = Zeroizing::new(passcode);
+        let keyfile_hash = read_keyfile(keyfile).await?;
+        self.decrypt_text_with(&input, Unlock::Passcode(&passcode, keyfile_hash))
+            .await
+    }
+
+    /// Decrypts a text trash encrypted to the public key of `secret_key`.
+    #[wasm_bindgen]
+    pub async fn decrypt_text_with_identity(
+        &self,
+        input: String,
+        secret_key: String,
+    ) -> Result<TextTrashContent, JsValue> {
+        self.decrypt_text_with(&input, Unlock::Identity(&secret_key))
+            .await
+    }
+
+    /// Decrypts a text trash from at least the threshold of its key shares.
+    #[wasm_bindgen]
+    pub async fn decrypt_text_with_shares(
+        &self,
+        input: String,
+        shares: Vec<String>,
+    ) -> Result<TextTrashContent, JsValue> {
+        self.decrypt_text_with(&input, Unlock::Shares(&shares))
+            .await
+    }
 
-        Ok(result_obj)
+    /// Creates a text trash encrypted directly with a caller-managed 256-bit key, given
+    /// as base64 or JWK. No key derivation happens and the key is never uploaded.
+    #[wasm_bindgen]
+    pub async fn encrypt_text_with_key(
+        &self,
+        content: String,
+        key: String,
+        options: Option<EncryptionOptions>,
+    ) -> Result<String, JsValue> {
+        let content = Zeroizing::new(content);
+        let key = Zeroizing::new(key);
+        let options = options.unwrap_or_default();
+        let data_key = Zeroizing::new(rawkey::parse_key(&key)?);
+        self.encrypt_text_with(&content, None, &data_key, KeySource::Raw, &options)
+            .await
+    }
+
+    /// Decrypts a text trash with its raw key, given as base64 or JWK.
+    #[wasm_bindgen]
+    pub async fn decrypt_text_with_key(
+        &self,
+        input: String,
+        key: String,
+    ) -> Result<TextTrashContent, JsValue> {
+        let key = Zeroizing::new(key);
+        let key = Zeroizing::new(rawkey::parse_key(&key)?);
+        self.decrypt_text_with(&input, Unlock::Key(&key)).await
     }
 
     #[wasm_bindgen]
@@ -91,121 +297,126 @@ impl TsbinController {
         options: Option<EncryptionOptions>,
         progress_callback: Option<js_sys::Function>,
     ) -> Result<String, JsValue> {
-        let options = options.unwrap_or_else(|| EncryptionOptions::new());
-        let chunk_size = options.chunk_size.unwrap_or(10 * 1024 * 1024);
-        let max_retries = options.max_retries.unwrap_or(3);
-
-        let file_size = file.size() as usize;
-        let total_chunks = (file_size + chunk_size - 1) / chunk_size;
-
-        let mut progress = UploadProgress {
-            total_chunks: total_chunks as u32,
-            uploaded_chunks: 0,
-            failed_chunks: Vec::new(),
-            completed: false,
-            trash_id: None,
-        };
-
-        let encryptor = Encryptor::new(passcode.clone());
-        let mut file_ids = Vec::new();
-        let mut message_ids = Vec::new();
-        let mut chunk_uploads: HashMap<u32, Result<ChunkUploadResponse, String>> = HashMap::new();
-
-        // Process chunks
-        for chunk_index in 0..total_chunks {
-            let start = chunk_index * chunk_size;
-            let end = std::cmp::min(start + chunk_size, file_size);
-
-            // Read chunk from file
-            let chunk_blob = file.slice_with_i32_and_i32(start as i32, end as i32)?;
-            let array_buffer =
-                wasm_bindgen_futures::JsFuture::from(chunk_blob.array_buffer()).await?;
-            let uint8_array = Uint8Array::new(&array_buffer);
-            let mut chunk_data = vec![0; uint8_array.length() as usize];
-            uint8_array.copy_to(&mut chunk_data);
-
-            // Encrypt chunk
-            let encrypted_chunk = encryptor.encrypt(&chunk_data);
-
-            // Upload with retries
-            let mut retries = 0;
-            let upload_result = loop {
-                match self
-                    .client
-                    .upload_chunk(&encrypted_chunk, chunk_index as u32)
-                    .await
-                {
-                    Ok(response) => break Ok(response),
-                    Err(e) => {
-                        console::log_1(&e);
-                        retries += 1;
-                        if retries >= max_retries {
-                            break Err(format!("Failed after {} retries: {:?}", max_retries, e));
-                        }
-                        // Simple delay for retry (in a real implementation, you might want exponential backoff)
-                        continue;
-                    }
-                }
-            };
-
-            match upload_result {
-                Ok(response) => {
-                    file_ids.push(response.file_id.clone());
-                    message_ids.push(response.message_id.clone());
-                    progress.uploaded_chunks += 1;
-                    chunk_uploads.insert(chunk_index as u32, Ok(response));
-                }
-                Err(e) => {
-                    progress.failed_chunks.push(chunk_index as u32);
-                    chunk_uploads.insert(chunk_index as u32, Err(e));
-                }
-            }
-
-            // Report progress
-            if let Some(ref callback) = progress_callback {
-                console::log_1(&JsValue::from_str("Reporting progress..."));
-                let progress_js = serde_wasm_bindgen::to_value(&progress)?;
-                console::log_1(&JsValue::from_str("Progress reported."));
-                callback.call1(&JsValue::null(), &progress_js)?;
-            }
-        }
+        let passcode = Zeroizing::new(passcode);
+        let options = options.unwrap_or_default();
+        let data_key = Zeroizing::new(keywrap::generate_data_key());
+        self.encrypt_file_with(
+            source::from_file(&file),
+            Some(&passcode),
+            &data_key,
+            KeySource::Random,
+            &options,
+            &progress_callback,
+        )
+        .await
+    }
 
-        // Check if all chunks uploaded successfully
-        if !progress.failed_chunks.is_empty() {
-            return Err(JsValue::from_str(&format!(
-                "Failed to upload {} chunks",
-                progress.failed_chunks.len()
-            )));
-        }
+    /// Like `encrypt_file`, for files already in memory, as in the Node.js package.
+    #[wasm_bindgen]
+    pub async fn encrypt_bytes(
+        &self,
+        data: Uint8Array,
+        file_name: String,
+        mime_type: Option<String>,
+        passcode: String,
+        options: Option<EncryptionOptions>,
+        progress_callback: Option<js_sys::Function>,
+    ) -> Result<String, JsValue> {
+        let passcode = Zeroizing::new(passcode);
+        let options = options.unwrap_or_default();
+        let data_key = Zeroizing::new(keywrap::generate_data_key());
+        self.encrypt_file_with(
+            source::from_bytes(&data, file_name, mime_type),
+            Some(&passcode),
+            &data_key,
+            KeySource::Random,
+            &options,
+            &progress_callback,
+        )
+        .await
+    }
 
-        // Create file trash
-        let metadata = json!({
-            "passcode_hash": utils::hash_passphrase(&passcode),
-            "expire_at": options.expire_at,
-            "original_size": file_size,
-            "total_chunks": total_chunks,
-            "chunk_size": chunk_size,
-            "encryption_type": "aes256gcm",
-            "filename": file.name(),
-            "mime_type": file.type_(),
-        });
+    /// Like `encrypt_bytes`, reading the file from an async iterable of `Uint8Array`s, such
+    /// as a Node.js `fs.ReadStream` or a `ReadableStream`, so it never sits in memory whole.
+    /// `total_chunks` in the progress grows as the stream is read.
+    #[wasm_bindgen]
+    pub async fn encrypt_stream(
+        &self,
+        chunks: JsValue,
+        file_name: String,
+        mime_type: Option<String>,
+        passcode: String,
+        options: Option<EncryptionOptions>,
+        progress_callback: Option<js_sys::Function>,
+    ) -> Result<String, JsValue> {
+        let passcode = Zeroizing::new(passcode);
+        let options = options.unwrap_or_default();
+        let data_key = Zeroizing::new(keywrap::generate_data_key());
+        self.encrypt_file_with(
+            source::from_stream(&chunks, file_name, mime_type)?,
+            Some(&passcode),
+            &data_key,
+            KeySource::Random,
+            &options,
+            &progress_callback,
+        )
+        .await
+    }
 
+    /// Creates a file trash without a passcode whose data key is split into `total` shares,
+    /// any `threshold` of which open it through `decrypt_file_with_shares`.
+    #[wasm_bindgen]
+    pub async fn encrypt_file_with_shares(
+        &self,
+        file: File,
+        threshold: u8,
+        total: u8,
+        options: Option<EncryptionOptions>,
+        progress_callback: Option<js_sys::Function>,
+    ) -> Result<SharedTrash, JsValue> {
+        let options = options.unwrap_or_default();
+        let data_key = Zeroizing::new(keywrap::generate_data_key());
+        let shares = trash::split_data_key(&data_key, threshold, total)?;
         let trash_id = self
-            .client
-            .create_file_trash(file_ids, message_ids, &metadata)
+            .encrypt_file_with(
+                source::from_file(&file),
+                None,
+                &data_key,
+                KeySource::Shares(threshold, total),
+                &options,
+                &progress_callback,
+            )
             .await?;
 
-        progress.completed = true;
-        progress.trash_id = Some(trash_id.clone());
+        Ok(SharedTrash { trash_id, shares })
+    }
 
-        // Final progress update
-        if let Some(ref callback) = progress_callback {
-            console::log_1(&JsValue::from_str("Reporting final progress..."));
-            let progress_js = serde_wasm_bindgen::to_value(&progress)?;
-            callback.call1(&JsValue::null(), &progress_js)?;
+    /// Uploads a file that is already encrypted with age as is. It is opened with the age
+    /// passphrase through `decrypt_file`, or with an age secret key through
+    /// `decrypt_file_with_identity`.
+    #[wasm_bindgen]
+    pub async fn upload_age_file(
+        &self,
+        file: File,
+        options: Option<EncryptionOptions>,
+        progress_callback: Option<js_sys::Function>,
+    ) -> Result<String, JsValue> {
+        let options = options.unwrap_or_default();
+        let header = file.slice_with_i32_and_i32(0, 32)?;
+        let header = JsFuture::from(header.array_buffer()).await?;
+        if !age::is_age(&Uint8Array::new(&header).to_vec()) {
+            return Err(JsValue::from_str("Not an age file"));
         }
 
-        Ok(trash_id)
+        let seal_options = seal_options(&options, None, KeySource::Random)?;
+        Ok(self
+            .session
+            .put_age_file(
+                source::from_file(&file),
+                &seal_options,
+                report(&progress_callback),
+            )
+            .await?)
     }
 
     #[wasm_bindgen]
@@ -214,73 +425,401 @@ impl TsbinController {
         trash_id: String,
         passcode: String,
         progress_callback: Option<js_sys::Function>,
+        keyfile: Option<File>,
+    ) -> Result<FileTrashContent, JsValue> {
+        let passcode = Zeroizing::new(passcode);
+        let keyfile_hash = read_keyfile(keyfile).await?;
+        self.decrypt_file_with(
+            trash_id,
+            Unlock::Passcode(&passcode, keyfile_hash),
+            progress_callback,
+        )
+        .await
+    }
+
+    /// Decrypts a file trash encrypted to the public key of `secret_key`.
+    #[wasm_bindgen]
+    pub async fn decrypt_file_with_identity(
+        &self,
+        trash_id: String,
+        secret_key: String,
+        progress_callback: Option<js_sys::Function>,
+    ) -> Result<FileTrashContent, JsValue> {
+        self.decrypt_file_with(trash_id, Unlock::Identity(&secret_key), progress_callback)
+            .await
+    }
+
+    /// Decrypts a file trash from at least the threshold of its key shares.
+    #[wasm_bindgen]
+    pub async fn decrypt_file_with_shares(
+        &self,
+        trash_id: String,
+        shares: Vec<String>,
+        progress_callback: Option<js_sys::Function>,
+    ) -> Result<FileTrashContent, JsValue> {
+        self.decrypt_file_with(trash_id, Unlock::Shares(&shares), progress_callback)
+            .await
+    }
+
+    /// Creates a file trash encrypted directly with a caller-managed 256-bit key, given
+    /// as base64 or JWK.
+    #[wasm_bindgen]
+    pub async fn encrypt_file_with_key(
+        &self,
+        file: File,
+        key: String,
+        options: Option<EncryptionOptions>,
+        progress_callback: Option<js_sys::Function>,
+    ) -> Result<String, JsValue> {
+        let key = Zeroizing::new(key);
+        let options = options.unwrap_or_default();
+        let data_key = Zeroizing::new(rawkey::parse_key(&key)?);
+        self.encrypt_file_with(
+            source::from_file(&file),
+            None,
+            &data_key,
+            KeySource::Raw,
+            &options,
+            &progress_callback,
+        )
+        .await
+    }
+
+    /// Decrypts a file trash with its raw key, given as base64 or JWK.
+    #[wasm_bindgen]
+    pub async fn decrypt_file_with_key(
+        &self,
+        trash_id: String,
+        key: String,
+        progress_callback: Option<js_sys::Function>,
     ) -> Result<FileTrashContent, JsValue> {
-        // Get trash metadata
-        let trash_meta = self.client.get_file_trash_meta(&trash_id).await?;
-
-        let file_ids = trash_meta
-            .file_ids
-            .ok_or_else(|| JsValue::from_str("No file IDs in trash"))?;
-        let total_chunks = file_ids.len();
-
-        let mut progress = UploadProgress {
-            total_chunks: total_chunks as u32,
-            uploaded_chunks: 0,
-            failed_chunks: Vec::new(),
-            completed: false,
-            trash_id: Some(trash_id.clone()),
+        let key = Zeroizing::new(key);
+        let key = Zeroizing::new(rawkey::parse_key(&key)?);
+        self.decrypt_file_with(trash_id, Unlock::Key(&key), progress_callback)
+            .await
+    }
+
+    /// Re-wraps the trash data key under a new passcode without touching the uploaded content.
+    /// Only the key slot opened by `old_passcode` is replaced.
+    #[wasm_bindgen]
+    pub async fn change_passcode(
+        &self,
+        trash_id: String,
+        old_passcode: String,
+        new_passcode: String,
+        keyfile: Option<File>,
+    ) -> Result<(), JsValue> {
+        let old_passcode = Zeroizing::new(old_passcode);
+        let new_passcode = Zeroizing::new(new_passcode);
+        let keyfile_hash = read_keyfile(keyfile).await?;
+        Ok(self
+            .session
+            .change_passcode(&trash_id, &old_passcode, &new_passcode, keyfile_hash)
+            .await?)
+    }
+
+    /// Adds a key slot so `new_passcode` also opens the trash. Returns the new slot id.
+    #[wasm_bindgen]
+    pub async fn add_key_slot(
+        &self,
+        trash_id: String,
+        passcode: String,
+        new_passcode: String,
+        keyfile: Option<File>,
+    ) -> Result<String, JsValue> {
+        let passcode = Zeroizing::new(passcode);
+        let new_passcode = Zeroizing::new(new_passcode);
+        let keyfile_hash = read_keyfile(keyfile).await?;
+        Ok(self
+            .session
+            .add_key_slot(&trash_id, &passcode, &new_passcode, keyfile_hash)
+            .await?)
+    }
+
+    /// Revokes the key slot `slot_id`; the passcode it belonged to no longer opens the trash.
+    /// Any remaining passcode can authorise the removal, but the last slot cannot be removed.
+    #[wasm_bindgen]
+    pub async fn remove_key_slot(
+        &self,
+        trash_id: String,
+        passcode: String,
+        slot_id: String,
+        keyfile: Option<File>,
+    ) -> Result<(), JsValue> {
+        let passcode = Zeroizing::new(passcode);
+        let keyfile_hash = read_keyfile(keyfile).await?;
+        Ok(self
+            .session
+            .remove_key_slot(&trash_id, &passcode, &slot_id, keyfile_hash)
+            .await?)
+    }
+}
+
+// Run with `wasm-pack test --node crates/wasm`
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+    use tsbin_core::{
+        mock::{
+            Fault, MockServer,
+            fixtures::{self, content, object_id},
+        },
+        transport::{Body, Part, Request, Transport},
+    };
+    use wasm_bindgen_test::wasm_bindgen_test;
+    use web_sys::{FormData, ResponseInit};
+
+    /// A controller whose fetch is answered by the mock API, so requests still go through
+    /// the real transport and its encoding.
+    fn controller() -> (TsbinController, Rc<MockServer>) {
+        let server = Rc::new(MockServer::new());
+        let controller = TsbinController {
+            session: Session::new(
+                TsbinClient::new(
+                    fixtures::BASE_URL.to_string(),
+                    String::new(),
+                    Fetch::Global(mock_fetch(server.clone())),
+                ),
+                Backend::detect(),
+            ),
         };
+        (controller, server)
+    }
 
-        let encryptor = Encryptor::new(passcode);
-        let mut decrypted_chunks: Vec<Vec<u8>> = vec![Vec::new(); total_chunks];
-
-        // Download and decrypt chunks
-        for (index, file_id) in file_ids.iter().enumerate() {
-            match self.client.download_chunk(&trash_id, file_id).await {
-                Ok(encrypted_chunk) => {
-                    let decrypted_chunk = encryptor.decrypt(&encrypted_chunk);
-                    decrypted_chunks[index] = decrypted_chunk;
-                    progress.uploaded_chunks += 1;
-                }
-                Err(_) => {
-                    progress.failed_chunks.push(index as u32);
-                }
-            }
+    /// A `fetch` function serving requests from `server`; a dropped request rejects.
+    fn mock_fetch(server: Rc<MockServer>) -> js_sys::Function {
+        let fetch = Closure::<dyn Fn(web_sys::Request) -> js_sys::Promise>::new(
+            move |request: web_sys::Request| {
+                let server = server.clone();
+                wasm_bindgen_futures::future_to_promise(async move {
+                    let response = server.send(mock_request(request).await?).await?;
+                    let init = ResponseInit::new();
+                    init.set_status(response.status);
+                    let mut body = response.body;
+                    let response =
+                        web_sys::Response::new_with_opt_u8_array_and_init(Some(&mut body), &init)?;
+                    Ok(response.into())
+                })
+            },
+        );
+        fetch.into_js_value().unchecked_into()
+    }
 
-            // Report progress
-            if let Some(ref callback) = progress_callback {
-                console::log_1(&JsValue::from_str("Reporting progress..."));
-                console::log_1(&JsValue::from_str(&format!("Progress: {:?}", progress)));
-                let progress_js = serde_wasm_bindgen::to_value(&progress)?;
-                callback.call1(&JsValue::null(), &progress_js)?;
+    /// Reads a request built by the fetch transport back into the form the mock API takes.
+    async fn mock_request(request: web_sys::Request) -> Result<Request, JsValue> {
+        let method = match request.method().as_str() {
+            "GET" => "GET",
+            "POST" => "POST",
+            "PATCH" => "PATCH",
+            "DELETE" => "DELETE",
+            other => return Err(JsValue::from_str(&format!("Unexpected method {}", other))),
+        };
+        let content_type = request.headers().get("Content-Type")?.unwrap_or_default();
+        let body = if content_type.starts_with("application/json") {
+            let text = JsFuture::from(request.text()?).await?;
+            let text = text.as_string().unwrap_or_default();
+            Body::Json(serde_json::from_str(&text).map_err(|e| JsValue::from_str(&e.to_string()))?)
+        } else if content_type.starts_with("multipart/form-data") {
+            let form: FormData = JsFuture::from(request.form_data()?).await?.unchecked_into();
+            let mut parts = Vec::new();
+            for entry in js_sys::try_iter(&form)?.expect("FormData is iterable") {
+                let entry: js_sys::Array = entry?.unchecked_into();
+                let name = match entry.get(0).as_string().as_deref() {
+                    Some("chunk") => "chunk",
+                    Some("chunk_index") => "chunk_index",
+                    _ => return Err(JsValue::from_str("Unexpected form field")),
+                };
+                let value = entry.get(1);
+                parts.push(match value.as_string() {
+                    Some(text) => Part {
+                        name,
+                        file_name: None,
+                        data: text.into_bytes(),
+                    },
+                    None => {
+                        let file: File = value.unchecked_into();
+                        let data = JsFuture::from(file.array_buffer()).await?;
+                        Part {
+                            name,
+                            file_name: Some(file.name()),
+                            data: Uint8Array::new(&data).to_vec(),
+                        }
+                    }
+                });
             }
-        }
+            Body::Multipart(parts)
+        } else {
+            Body::Empty
+        };
+        Ok(Request {
+            method,
+            url: request.url(),
+            headers: Vec::new(),
+            body,
+        })
+    }
 
-        if !progress.failed_chunks.is_empty() {
-            return Err(JsValue::from_str(&format!(
-                "Failed to download {} chunks",
-                progress.failed_chunks.len()
-            )));
-        }
+    fn options(chunk_size: usize) -> Option<EncryptionOptions> {
+        let mut options = EncryptionOptions::default();
+        options.chunk_size = Some(chunk_size);
+        Some(options)
+    }
 
-        // Combine all chunks
-        let mut final_data = Vec::new();
-        for chunk in decrypted_chunks {
-            final_data.extend_from_slice(&chunk);
-        }
+    /// A progress callback along with the number of times it was called.
+    fn progress() -> (js_sys::Function, Rc<Cell<u32>>) {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let callback = Closure::<dyn FnMut(JsValue)>::new(move |_: JsValue| {
+            counter.set(counter.get() + 1);
+        });
+        (callback.into_js_value().unchecked_into(), calls)
+    }
+
+    async fn encrypt_bytes(controller: &TsbinController, data: &[u8]) -> Result<String, JsValue> {
+        controller
+            .encrypt_bytes(
+                Uint8Array::from(data),
+                "data.bin".to_string(),
+                None,
+                "pw".to_string(),
+                options(1000),
+                None,
+            )
+            .await
+    }
+
+    /// Opens the file trash `slug` by its object id, as the web app does once the slug is
+    /// resolved.
+    async fn decrypt_file(
+        controller: &TsbinController,
+        slug: &str,
+        passcode: &str,
+    ) -> Result<FileTrashContent, JsValue> {
+        let object_id = object_id(controller.session.client(), slug).await;
+        controller
+            .decrypt_file(object_id, passcode.to_string(), None, None)
+            .await
+    }
+
+    #[wasm_bindgen_test]
+    async fn text_round_trip() {
+        let (controller, _) = controller();
+        let slug = controller
+            .encrypt_text("hello".to_string(), "pw".to_string(), None)
+            .await
+            .unwrap();
+        let object_id = object_id(controller.session.client(), &slug).await;
+
+        let content = controller
+            .decrypt_text(object_id.clone(), "pw".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(content.enc_trash_text, "hello");
+        assert!(
+            controller
+                .decrypt_text(object_id, "wrong".to_string(), None)
+                .await
+                .is_err()
+        );
+    }
+
+    #[wasm_bindgen_test]
+    async fn file_round_trip_reports_progress() {
+        let (controller, server) = controller();
+        server.inject(Fault::Delay(3));
+        let data = content(3500);
+        let (callback, calls) = progress();
+        let trash_id = controller
+            .encrypt_bytes(
+                Uint8Array::from(&data[..]),
+                "notes.txt".to_string(),
+                Some("text/plain".to_string()),
+                "pw".to_string(),
+                options(1000),
+                Some(callback),
+            )
+            .await
+            .unwrap();
+        // One update per chunk and a final one
+        assert_eq!(calls.get(), 5);
+
+        let file = decrypt_file(&controller, &trash_id, "pw").await.unwrap();
+        assert_eq!(file.file, data);
+        assert_eq!(file.file_name, "notes.txt");
+        assert_eq!(file.mime_type, "text/plain");
+    }
+
+    #[wasm_bindgen_test]
+    async fn stream_is_regrouped_into_chunks() {
+        let (controller, server) = controller();
+        let pieces = js_sys::Function::new_no_args(
+            "return (async function* () {
+                for (let i = 0; i < 7; i++) yield new Uint8Array(300).fill(i);
+            })();",
+        )
+        .call0(&JsValue::undefined())
+        .unwrap();
+        let trash_id = controller
+            .encrypt_stream(
+                pieces,
+                "stream.bin".to_string(),
+                None,
+                "pw".to_string(),
+                options(1000),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(server.chunk_count(), 3);
+
+        let file = decrypt_file(&controller, &trash_id, "pw").await.unwrap();
+        let expected: Vec<u8> = (0..7).flat_map(|i| [i as u8; 300]).collect();
+        assert_eq!(file.file, expected);
+    }
+
+    #[wasm_bindgen_test]
+    async fn dropped_chunks_are_retried() {
+        let (controller, server) = controller();
+        server.inject(Fault::DropChunk(1));
+        server.inject(Fault::DropChunk(1));
+        let data = content(2500);
+        let trash_id = encrypt_bytes(&controller, &data).await.unwrap();
 
-        progress.completed = true;
-        if let Some(ref callback) = progress_callback {
-            let progress_js = serde_wasm_bindgen::to_value(&progress)?;
-            callback.call1(&JsValue::null(), &progress_js)?;
+        let file = decrypt_file(&controller, &trash_id, "pw").await.unwrap();
+        assert_eq!(file.file, data);
+    }
+
+    #[wasm_bindgen_test]
+    async fn upload_fails_after_repeated_server_errors() {
+        let (controller, server) = controller();
+        for _ in 0..3 {
+            server.inject(Fault::ServerError("/trash/chunk"));
         }
+        assert!(encrypt_bytes(&controller, &content(10)).await.is_err());
+    }
 
-        Ok(FileTrashContent {
-            id: trash_id,
-            file: final_data,
-            mime_type: trash_meta.mime_type.clone(),
-            file_name: trash_meta.file_name.clone(),
-            file_size: trash_meta.file_size,
-        })
+    #[wasm_bindgen_test]
+    async fn reordered_chunks_are_rejected() {
+        let (controller, server) = controller();
+        let trash_id = encrypt_bytes(&controller, &content(2500)).await.unwrap();
+        assert!(decrypt_file(&controller, &trash_id, "pw").await.is_ok());
+
+        server.inject(Fault::ReorderChunks);
+        assert!(decrypt_file(&controller, &trash_id, "pw").await.is_err());
+    }
+
+    #[wasm_bindgen_test]
+    async fn changed_passcode_replaces_the_old_one() {
+        let (controller, _) = controller();
+        let trash_id = encrypt_bytes(&controller, &content(10)).await.unwrap();
+        controller
+            .change_passcode(trash_id.clone(), "pw".to_string(), "new".to_string(), None)
+            .await
+            .unwrap();
+
+        assert!(decrypt_file(&controller, &trash_id, "pw").await.is_err());
+        let file = decrypt_file(&controller, &trash_id, "new").await.unwrap();
+        assert_eq!(file.file, content(10));
     }
 }
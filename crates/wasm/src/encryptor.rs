@@ -1,83 +1,76 @@
-use crate::utils;
-use aes_gcm::{
-    Aes256Gcm,
-    aead::{Aead, KeyInit},
-};
-
-use generic_array::GenericArray;
+use crate::backend::Backend;
+use tsbin_core::{Result, encryptor};
 use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
 
 #[wasm_bindgen]
 pub struct Encryptor {
-    key: aes_gcm::Key<Aes256Gcm>,
+    inner: encryptor::Encryptor,
 }
 
-const CHUNK_SIZE: usize = 5 * 1024 * 1024; // 5MB
-
 #[wasm_bindgen]
 impl Encryptor {
     #[wasm_bindgen(constructor)]
-    pub fn new(passphrase: String) -> Self {
-        let key_bytes = utils::derive_key(&passphrase);
+    pub fn new(passphrase: String, keyfile: Option<Vec<u8>>) -> Self {
+        let passphrase = Zeroizing::new(passphrase);
+        let keyfile = keyfile.map(Zeroizing::new);
         Self {
-            key: key_bytes.into(),
+            inner: encryptor::Encryptor::new(&passphrase, keyfile.as_deref().map(Vec::as_slice)),
         }
     }
-    #[wasm_bindgen]
-    pub fn encrypt(&self, data: &[u8]) -> Vec<u8> {
-        let cipher = Aes256Gcm::new(&self.key);
-        let mut output = Vec::new();
-        let mut chunk_index: u64 = 0;
 
-        for chunk in data.chunks(CHUNK_SIZE) {
-            let mut nonce_bytes = [0u8; 12];
-            nonce_bytes[..8].copy_from_slice(&chunk_index.to_be_bytes());
-            let nonce = GenericArray::from_slice(&nonce_bytes);
+    /// Wipes the key from wasm memory. The encryptor cannot be used afterwards.
+    #[wasm_bindgen]
+    pub fn dispose(self) {}
 
-            let ciphertext = cipher
-                .encrypt(nonce.as_0_14(), chunk)
-                .expect("encryption failure");
+    /// Uses 32 raw key bytes as they are, skipping passphrase derivation.
+    #[wasm_bindgen]
+    pub fn from_raw_key(key: &[u8]) -> Result<Encryptor> {
+        encryptor::Encryptor::from_raw_key(key).map(|inner| Self { inner })
+    }
 
-            output.extend_from_slice(&nonce_bytes);
-            output.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
-            output.extend_from_slice(&ciphertext);
-            chunk_index += 1;
-        }
-        output
+    /// Imports a key exported by `export_key` or `export_jwk`.
+    #[wasm_bindgen]
+    pub fn import_key(key: &str) -> Result<Encryptor> {
+        encryptor::Encryptor::import_key(key).map(|inner| Self { inner })
     }
 
+    /// The key in use, base64 encoded, so it can be cached instead of re-derived.
     #[wasm_bindgen]
-    pub fn decrypt(&self, data: &[u8]) -> Vec<u8> {
-        let cipher = Aes256Gcm::new(&self.key);
-        let mut cursor = 0;
-        let mut output = Vec::new();
+    pub fn export_key(&self) -> String {
+        self.inner.export_key()
+    }
 
-        while cursor < data.len() {
-            if cursor + 12 + 4 > data.len() {
-                break;
-            }
+    /// The key in use as a WebCrypto compatible AES-GCM JWK.
+    #[wasm_bindgen]
+    pub fn export_jwk(&self) -> String {
+        self.inner.export_jwk()
+    }
 
-            let nonce_bytes = &data[cursor..cursor + 12];
-            cursor += 12;
+    #[wasm_bindgen]
+    pub fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+        self.inner.encrypt(data)
+    }
 
-            let len_bytes = &data[cursor..cursor + 4];
-            cursor += 4;
-            let chunk_len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
-            // Check that ciphertext fits in remaining data
-            if cursor + chunk_len > data.len() {
-                // return Err("Chunk truncated or corrupted");
-                break;
-            }
+    #[wasm_bindgen]
+    pub fn decrypt(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        self.inner.decrypt(data)
+    }
 
-            let ciphertext = &data[cursor..cursor + chunk_len];
-            cursor += chunk_len;
+    /// `encrypt` on SubtleCrypto where the runtime has it, without blocking the thread.
+    #[wasm_bindgen]
+    pub async fn encrypt_async(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let data = Zeroizing::new(data);
+        self.inner
+            .encrypt_with(&Backend::detect(), data.len(), |offset, dst| {
+                dst.copy_from_slice(&data[offset..offset + dst.len()])
+            })
+            .await
+    }
 
-            let nonce = GenericArray::from_slice(nonce_bytes);
-            let plaintext = cipher
-                .decrypt(nonce.as_0_14(), ciphertext)
-                .expect("decryption failure");
-            output.extend_from_slice(&plaintext);
-        }
-        output
+    /// `decrypt` on SubtleCrypto where the runtime has it, without blocking the thread.
+    #[wasm_bindgen]
+    pub async fn decrypt_async(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        self.inner.decrypt_with(&Backend::detect(), data).await
     }
 }
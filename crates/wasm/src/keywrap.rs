@@ -0,0 +1,57 @@
+use crate::backend::Backend;
+use tsbin_core::{Result, keywrap, utils};
+use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
+
+/// A passcode, and keyfile if any, stretched once under a random salt that every slot it
+/// seals records. It seals and opens any number of those slots without running PBKDF2
+/// again, and is zeroized on drop.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct DerivedKey {
+    inner: keywrap::DerivedKey,
+}
+
+impl DerivedKey {
+    pub(crate) fn into_inner(self) -> keywrap::DerivedKey {
+        self.inner
+    }
+}
+
+#[wasm_bindgen]
+impl DerivedKey {
+    /// The key as a string that `DerivedKey.import` accepts. It opens every trash the
+    /// passcode opens, so it must be kept as carefully as the passcode.
+    #[wasm_bindgen]
+    pub fn export(&self) -> String {
+        self.inner.export()
+    }
+
+    /// Wipes the key from wasm memory. It cannot be used afterwards.
+    #[wasm_bindgen]
+    pub fn dispose(self) {}
+
+    #[wasm_bindgen]
+    pub fn import(exported: &str) -> Result<DerivedKey> {
+        keywrap::DerivedKey::import(exported).map(|inner| Self { inner })
+    }
+}
+
+/// Runs the passcode key derivation as its own async step, on SubtleCrypto where available,
+/// so it can be done once — in a Web Worker if need be — and reused across operations.
+#[wasm_bindgen]
+pub async fn derive_passcode_key(passcode: String, keyfile: Option<Vec<u8>>) -> Result<DerivedKey> {
+    let passcode = Zeroizing::new(passcode);
+    let keyfile = keyfile.map(Zeroizing::new);
+    let keyfile_hash = keyfile
+        .as_deref()
+        .map(|keyfile| Zeroizing::new(utils::hash_keyfile(keyfile)));
+    let inner = keywrap::DerivedKey::derive(
+        &Backend::detect(),
+        &passcode,
+        utils::random_bytes(),
+        keyfile_hash.as_deref(),
+    )
+    .await?;
+    Ok(DerivedKey { inner })
+}
@@ -0,0 +1,19 @@
+use tsbin_core::{
+    Result,
+    legacy::{self, LegacyFile},
+};
+use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
+
+/// Whether `data` could be a file written by the old Node CLI, for `decrypt_legacy_file`.
+#[wasm_bindgen]
+pub fn is_legacy_file(data: &[u8]) -> bool {
+    legacy::is_legacy(data)
+}
+
+/// Decrypts a file written by the old Node CLI and recovers its original name.
+#[wasm_bindgen]
+pub fn decrypt_legacy_file(data: &[u8], passphrase: String) -> Result<LegacyFile> {
+    let passphrase = Zeroizing::new(passphrase);
+    legacy::decrypt(data, &passphrase)
+}
@@ -1,9 +1,27 @@
-mod client;
+mod age;
+mod backend;
 mod controller;
 mod encryptor;
+mod keywrap;
+mod legacy;
+mod recipient;
+mod signing;
+mod source;
+mod transport;
 mod types;
 mod utils;
+mod worker;
 
+pub use age::{
+    age_decrypt_with_identity, age_decrypt_with_passphrase, age_encrypt_to_recipients,
+    age_encrypt_with_passphrase,
+};
 pub use controller::*;
+pub use encryptor::Encryptor;
+pub use keywrap::{DerivedKey, derive_passcode_key};
+pub use legacy::{decrypt_legacy_file, is_legacy_file};
+pub use recipient::{KeyPair, generate_keypair, import_secret_key};
+pub use signing::{SigningKeyPair, generate_signing_key, import_signing_key, signer_fingerprint};
 pub use types::*;
 pub use utils::*;
+pub use worker::TsbinWorker;
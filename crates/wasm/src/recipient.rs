@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use tsbin_core::{Result, recipient, utils};
+use wasm_bindgen::prelude::*;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// An X25519 keypair encoded like age keys: `age1...` for the public key
+/// and `AGE-SECRET-KEY-1...` for the secret key.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyPair {
+    pub public_key: String,
+    pub secret_key: String,
+}
+
+impl From<&StaticSecret> for KeyPair {
+    fn from(secret: &StaticSecret) -> Self {
+        KeyPair {
+            public_key: recipient::encode_public_key(&PublicKey::from(secret)),
+            secret_key: recipient::encode_secret_key(secret),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn generate_keypair() -> KeyPair {
+    KeyPair::from(&StaticSecret::from(utils::random_bytes::<32>()))
+}
+
+/// Imports a secret key and returns the keypair it belongs to.
+#[wasm_bindgen]
+pub fn import_secret_key(secret_key: &str) -> Result<KeyPair> {
+    recipient::parse_secret_key(secret_key).map(|secret| KeyPair::from(&secret))
+}
@@ -0,0 +1,41 @@
+use base64::{Engine, engine::general_purpose};
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use tsbin_core::{Result, signing, utils};
+use wasm_bindgen::prelude::*;
+
+/// An Ed25519 keypair for signing trashes. Both keys are base64 encoded.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningKeyPair {
+    pub public_key: String,
+    pub secret_key: String,
+    pub fingerprint: String,
+}
+
+impl From<&SigningKey> for SigningKeyPair {
+    fn from(key: &SigningKey) -> Self {
+        SigningKeyPair {
+            public_key: general_purpose::STANDARD.encode(key.verifying_key().as_bytes()),
+            secret_key: general_purpose::STANDARD.encode(key.to_bytes()),
+            fingerprint: signing::fingerprint(&key.verifying_key()),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn generate_signing_key() -> SigningKeyPair {
+    SigningKeyPair::from(&SigningKey::from_bytes(&utils::random_bytes::<32>()))
+}
+
+/// Imports a base64 secret signing key and returns the keypair it belongs to.
+#[wasm_bindgen]
+pub fn import_signing_key(secret_key: &str) -> Result<SigningKeyPair> {
+    signing::parse_signing_key(secret_key).map(|key| SigningKeyPair::from(&key))
+}
+
+/// The fingerprint shown for a signer, in the `SHA256:...` form ssh uses.
+#[wasm_bindgen]
+pub fn signer_fingerprint(public_key: &str) -> Result<String> {
+    signing::parse_verifying_key(public_key).map(|key| signing::fingerprint(&key))
+}
@@ -0,0 +1,175 @@
+//! Where the plaintext of a new file trash is read from: a browser `File`, bytes already
+//! in memory, or an async iterable of `Uint8Array`s such as a Node or web stream.
+
+use crate::utils::js_error;
+use js_sys::{AsyncIterator, Reflect, Symbol, Uint8Array};
+use tsbin_core::trash::{self, NewFile, Plaintext};
+use wasm_bindgen::{JsCast, prelude::*};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::File;
+use zeroize::Zeroizing;
+
+pub(crate) fn from_file(file: &File) -> NewFile<ChunkSource<'_>> {
+    NewFile {
+        name: file.name(),
+        mime_type: file.type_(),
+        source: ChunkSource::file(file),
+    }
+}
+
+pub(crate) fn from_bytes(
+    data: &Uint8Array,
+    name: String,
+    mime_type: Option<String>,
+) -> NewFile<ChunkSource<'_>> {
+    NewFile {
+        name,
+        mime_type: mime_type.unwrap_or_default(),
+        source: ChunkSource::bytes(data),
+    }
+}
+
+pub(crate) fn from_stream<'a>(
+    chunks: &JsValue,
+    name: String,
+    mime_type: Option<String>,
+) -> Result<NewFile<ChunkSource<'a>>, JsValue> {
+    Ok(NewFile {
+        name,
+        mime_type: mime_type.unwrap_or_default(),
+        source: ChunkSource::stream(chunks)?,
+    })
+}
+
+/// A chunk read out of JS memory, copied into wasm memory only where it is sealed.
+pub(crate) struct JsChunk(Uint8Array);
+
+impl Plaintext for JsChunk {
+    fn size(&self) -> usize {
+        self.0.length() as usize
+    }
+
+    fn copy_to(&self, offset: usize, dst: &mut [u8]) {
+        self.0
+            .subarray(offset as u32, (offset + dst.len()) as u32)
+            .copy_to(dst)
+    }
+}
+
+pub(crate) enum ChunkSource<'a> {
+    File {
+        file: &'a File,
+        position: usize,
+    },
+    Bytes {
+        data: &'a Uint8Array,
+        position: usize,
+    },
+    /// Regrouped into chunks of the upload size as the pieces come in
+    Stream {
+        iterator: AsyncIterator,
+        pending: Zeroizing<Vec<u8>>,
+        done: bool,
+    },
+}
+
+impl<'a> ChunkSource<'a> {
+    pub(crate) fn file(file: &'a File) -> Self {
+        ChunkSource::File { file, position: 0 }
+    }
+
+    pub(crate) fn bytes(data: &'a Uint8Array) -> Self {
+        ChunkSource::Bytes { data, position: 0 }
+    }
+
+    pub(crate) fn stream(iterable: &JsValue) -> Result<Self, JsValue> {
+        let iterator = Reflect::get(iterable, &Symbol::async_iterator())?
+            .dyn_into::<js_sys::Function>()
+            .map_err(|_| JsValue::from_str("Not an async iterable"))?
+            .call0(iterable)?
+            .unchecked_into();
+        Ok(ChunkSource::Stream {
+            iterator,
+            pending: Zeroizing::new(Vec::new()),
+            done: false,
+        })
+    }
+
+    async fn read_chunk(&mut self, chunk_size: usize) -> Result<Option<Uint8Array>, JsValue> {
+        match self {
+            ChunkSource::File { file, position } => {
+                let end = std::cmp::min(*position + chunk_size, file.size() as usize);
+                if *position >= end {
+                    return Ok(None);
+                }
+                let chunk_blob = file.slice_with_f64_and_f64(*position as f64, end as f64)?;
+                let array_buffer = JsFuture::from(chunk_blob.array_buffer()).await?;
+                *position = end;
+                Ok(Some(Uint8Array::new(&array_buffer)))
+            }
+            ChunkSource::Bytes { data, position } => {
+                let end = std::cmp::min(*position + chunk_size, data.length() as usize);
+                if *position >= end {
+                    return Ok(None);
+                }
+                let chunk = data.subarray(*position as u32, end as u32);
+                *position = end;
+                Ok(Some(chunk))
+            }
+            ChunkSource::Stream {
+                iterator,
+                pending,
+                done,
+            } => {
+                while pending.len() < chunk_size && !*done {
+                    let next = JsFuture::from(iterator.next()?).await?;
+                    if Reflect::get(&next, &"done".into())?.is_truthy() {
+                        *done = true;
+                    } else {
+                        let piece = Reflect::get(&next, &"value".into())?
+                            .dyn_into::<Uint8Array>()
+                            .map_err(|_| JsValue::from_str("Streams must yield Uint8Arrays"))?;
+                        let start = pending.len();
+                        let len = start + piece.length() as usize;
+                        if len > pending.capacity() {
+                            // Moved by hand: a reallocation would leave the old plaintext
+                            // behind unwiped
+                            let mut grown = Vec::with_capacity(len.max(chunk_size));
+                            grown.extend_from_slice(&pending[..]);
+                            *pending = Zeroizing::new(grown);
+                        }
+                        pending.resize(len, 0);
+                        piece.copy_to(&mut pending[start..]);
+                    }
+                }
+                if pending.is_empty() {
+                    return Ok(None);
+                }
+                let end = std::cmp::min(chunk_size, pending.len());
+                let chunk = Uint8Array::from(&pending[..end]);
+                pending.copy_within(end.., 0);
+                let rest = pending.len() - end;
+                pending[rest..].iter_mut().for_each(|byte| *byte = 0);
+                pending.truncate(rest);
+                Ok(Some(chunk))
+            }
+        }
+    }
+}
+
+impl trash::ChunkSource for ChunkSource<'_> {
+    type Chunk = JsChunk;
+
+    fn size(&self) -> Option<usize> {
+        match self {
+            ChunkSource::File { file, .. } => Some(file.size() as usize),
+            ChunkSource::Bytes { data, .. } => Some(data.length() as usize),
+            ChunkSource::Stream { .. } => None,
+        }
+    }
+
+    async fn next_chunk(&mut self, chunk_size: usize) -> tsbin_core::Result<Option<JsChunk>> {
+        let chunk = self.read_chunk(chunk_size).await.map_err(js_error)?;
+        Ok(chunk.map(JsChunk))
+    }
+}
@@ -0,0 +1,130 @@
+//! fetch as a tsbin-core `Transport`, taken from the browser window, from the global
+//! scope of a worker, or from `globalThis` in other runtimes.
+
+use js_sys::Uint8Array;
+use tsbin_core::{
+    Error, Result,
+    transport::{Body, Request, Response, Transport},
+};
+use wasm_bindgen::{JsCast, prelude::*};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, FormData, Headers, RequestInit, RequestMode, Window, WorkerGlobalScope};
+
+fn transport_error(error: JsValue) -> Error {
+    Error::Transport(error.as_string().unwrap_or_else(|| format!("{:?}", error)))
+}
+
+pub(crate) enum Fetch {
+    Window(Window),
+    /// Dedicated, shared and Service Workers alike
+    Worker(WorkerGlobalScope),
+    /// `globalThis.fetch`, for runtimes that are neither
+    Global(js_sys::Function),
+}
+
+impl Fetch {
+    pub(crate) fn window() -> Result<Self> {
+        web_sys::window()
+            .map(Fetch::Window)
+            .ok_or_else(|| Error::invalid("There is no window here"))
+    }
+
+    pub(crate) fn worker() -> Result<Self> {
+        js_sys::global()
+            .dyn_into::<WorkerGlobalScope>()
+            .map(Fetch::Worker)
+            .map_err(|_| Error::invalid("Not running in a worker"))
+    }
+
+    pub(crate) fn global() -> Result<Self> {
+        js_sys::Reflect::get(&js_sys::global(), &"fetch".into())
+            .ok()
+            .and_then(|fetch| fetch.dyn_into::<js_sys::Function>().ok())
+            .map(Fetch::Global)
+            .ok_or_else(|| Error::invalid("fetch is not available here"))
+    }
+
+    /// Whichever fetch the current global scope offers.
+    pub(crate) fn detect() -> Result<Self> {
+        Self::window()
+            .or_else(|_| Self::worker())
+            .or_else(|_| Self::global())
+    }
+
+    /// The transport named when the controller is constructed; detected by default.
+    pub(crate) fn named(name: Option<&str>) -> Result<Self> {
+        match name {
+            None => Self::detect(),
+            Some("window") => Self::window(),
+            Some("worker") => Self::worker(),
+            Some("global") => Self::global(),
+            Some(other) => Err(Error::invalid(format!("Unknown transport: {}", other))),
+        }
+    }
+
+    fn fetch(&self, request: &web_sys::Request) -> Result<js_sys::Promise> {
+        match self {
+            Fetch::Window(window) => Ok(window.fetch_with_request(request)),
+            Fetch::Worker(worker) => Ok(worker.fetch_with_request(request)),
+            Fetch::Global(fetch) => fetch
+                .call1(&JsValue::undefined(), request)
+                .map(JsCast::unchecked_into)
+                .map_err(transport_error),
+        }
+    }
+}
+
+impl Transport for Fetch {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let opts = RequestInit::new();
+        opts.set_method(request.method);
+        opts.set_mode(RequestMode::Cors);
+
+        let headers = Headers::new().map_err(transport_error)?;
+        for (name, value) in &request.headers {
+            headers.set(name, value).map_err(transport_error)?;
+        }
+        match request.body {
+            Body::Empty => {}
+            Body::Json(body) => {
+                opts.set_body(&JsValue::from_str(&serde_json::to_string(&body)?));
+                headers
+                    .set("Content-Type", "application/json")
+                    .map_err(transport_error)?;
+            }
+            Body::Multipart(parts) => {
+                let form_data = FormData::new().map_err(transport_error)?;
+                for part in parts {
+                    match part.file_name {
+                        Some(file_name) => {
+                            let data = Uint8Array::from(&part.data[..]);
+                            let blob = Blob::new_with_u8_array_sequence(&js_sys::Array::of1(&data))
+                                .map_err(transport_error)?;
+                            form_data.append_with_blob_and_filename(part.name, &blob, &file_name)
+                        }
+                        None => form_data
+                            .append_with_str(part.name, &String::from_utf8_lossy(&part.data)),
+                    }
+                    .map_err(transport_error)?;
+                }
+                opts.set_body(&form_data);
+            }
+        }
+        opts.set_headers(&headers);
+
+        let request = web_sys::Request::new_with_str_and_init(&request.url, &opts)
+            .map_err(transport_error)?;
+        let response: web_sys::Response = JsFuture::from(self.fetch(&request)?)
+            .await
+            .map_err(transport_error)?
+            .unchecked_into();
+        let body = JsFuture::from(response.array_buffer().map_err(transport_error)?)
+            .await
+            .map_err(transport_error)?;
+
+        Ok(Response {
+            status: response.status(),
+            body: Uint8Array::new(&body).to_vec(),
+        })
+    }
+}
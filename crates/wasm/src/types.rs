@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
+pub use tsbin_core::types::{ChunkUploadResponse, TrashMeta, UploadProgress};
+use tsbin_core::utils;
 use wasm_bindgen::prelude::*;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 #[wasm_bindgen(getter_with_clone)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +12,8 @@ pub struct FileTrashContent {
     pub file: Vec<u8>,
     pub file_name: String,
     pub file_size: usize,
+    /// Fingerprint of the key that signed the trash, when it was signed
+    pub signer: Option<String>,
 }
 
 #[wasm_bindgen(getter_with_clone)]
@@ -18,6 +23,8 @@ pub struct TextTrashContent {
     pub enc_trash_text: String,
     pub encryption_type: String,
     pub text_length: usize,
+    /// Fingerprint of the key that signed the trash, when it was signed
+    pub signer: Option<String>,
 }
 
 #[wasm_bindgen]
@@ -27,8 +34,9 @@ impl TextTrashContent {
     }
 }
 
+/// Zeroized on drop, as it carries extra passcodes and the keyfile hash.
 #[wasm_bindgen]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
 pub struct EncryptionOptions {
     #[wasm_bindgen(skip)]
     pub expire_at: Option<String>,
@@ -36,6 +44,29 @@ pub struct EncryptionOptions {
     pub chunk_size: Option<usize>,
     #[wasm_bindgen(skip)]
     pub max_retries: Option<u32>,
+    /// Extra passcodes that can open the trash, each stored in its own key slot
+    #[wasm_bindgen(skip)]
+    #[serde(default)]
+    pub passcodes: Vec<String>,
+    /// `age1...` public keys the trash is encrypted to, in addition to the passcodes
+    #[wasm_bindgen(skip)]
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    /// Base64 Ed25519 secret key the trash is signed with
+    #[wasm_bindgen(skip)]
+    pub signing_key: Option<String>,
+    /// SHA-256 of the keyfile every passcode has to be combined with
+    #[wasm_bindgen(skip)]
+    pub keyfile_hash: Option<[u8; 32]>,
+    /// `aes256gcm` (the default) or `xchacha20poly1305`
+    #[wasm_bindgen(skip)]
+    pub encryption_type: Option<String>,
+}
+
+impl Default for EncryptionOptions {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
@@ -46,6 +77,11 @@ impl EncryptionOptions {
             expire_at: None,
             chunk_size: Some(10 * 1024 * 1024), // 10MB default
             max_retries: Some(3),
+            passcodes: Vec::new(),
+            recipients: Vec::new(),
+            signing_key: None,
+            keyfile_hash: None,
+            encryption_type: None,
         }
     }
 
@@ -63,39 +99,41 @@ impl EncryptionOptions {
     pub fn set_max_retries(&mut self, max_retries: Option<u32>) {
         self.max_retries = max_retries;
     }
-}
 
-#[wasm_bindgen(getter_with_clone)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TrashMeta {
-    pub trash_id: String,
-    pub encrypted: bool,
-    pub trash_type: String, // "file" | "text"
-    pub expire_at: Option<String>,
-    pub file_ids: Option<Vec<String>>,
-    pub message_ids: Option<Vec<u64>>,
-    pub total_chunks: Option<u32>,
-    pub total_size: Option<u64>,
+    #[wasm_bindgen(setter)]
+    pub fn set_passcodes(&mut self, passcodes: Vec<String>) {
+        self.passcodes = passcodes;
+    }
 
-    pub file_name: String,
-    pub mime_type: String,
-    pub file_size: usize,
-}
+    #[wasm_bindgen(setter)]
+    pub fn set_recipients(&mut self, recipients: Vec<String>) {
+        self.recipients = recipients;
+    }
 
-#[wasm_bindgen(getter_with_clone)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChunkUploadResponse {
-    pub file_id: String,
-    pub message_id: u32,
-    pub chunk_index: u32,
+    #[wasm_bindgen(setter)]
+    pub fn set_signing_key(&mut self, signing_key: Option<String>) {
+        self.signing_key = signing_key;
+    }
+
+    /// Requires the contents of this keyfile next to the passcodes; only its hash is kept.
+    #[wasm_bindgen(setter)]
+    pub fn set_keyfile(&mut self, keyfile: Option<Vec<u8>>) {
+        let keyfile = keyfile.map(Zeroizing::new);
+        self.keyfile_hash = keyfile
+            .as_deref()
+            .map(|keyfile| utils::hash_keyfile(keyfile));
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_encryption_type(&mut self, encryption_type: Option<String>) {
+        self.encryption_type = encryption_type;
+    }
 }
 
+/// A trash whose data key was split, with the printable shares to hand out.
 #[wasm_bindgen(getter_with_clone)]
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct UploadProgress {
-    pub total_chunks: u32,
-    pub uploaded_chunks: u32,
-    pub failed_chunks: Vec<u32>,
-    pub completed: bool,
-    pub trash_id: Option<String>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedTrash {
+    pub trash_id: String,
+    pub shares: Vec<String>,
 }
@@ -0,0 +1,205 @@
+//! A `postMessage` front end to `TsbinController` for Web Workers and Service Workers, so
+//! encryption runs off the page's main thread.
+//!
+//! Requests are plain objects `{ id, op, ... }`:
+//!
+//! - `encrypt_text`: `content`, `passcode`, `options`
+//! - `decrypt_text`: `trash_id`, `passcode`, `keyfile`
+//! - `encrypt_file`: `file`, or `data` (an `ArrayBuffer`) with `file_name` and `mime_type`,
+//!   then `passcode` and `options`
+//! - `decrypt_file`: `trash_id`, `passcode`, `keyfile`
+//!
+//! `options` holds the `EncryptionOptions` fields as a plain object and `keyfile` is a
+//! `File`. Each request is answered with `{ id, type: "progress", progress }` after every
+//! chunk, then one `{ id, type: "result", result }` or `{ id, type: "error", message }`.
+//! Decrypted files come back as a transferred `ArrayBuffer` in `result.file`.
+
+use crate::{controller::TsbinController, types::EncryptionOptions};
+use js_sys::{Array, ArrayBuffer, Function, Object, Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, prelude::*};
+use web_sys::{File, FilePropertyBag};
+
+#[wasm_bindgen]
+pub struct TsbinWorker {
+    controller: TsbinController,
+}
+
+/// Where the answers to one request go.
+#[derive(Clone)]
+struct Reply {
+    target: JsValue,
+    id: JsValue,
+}
+
+impl Reply {
+    fn post(
+        &self,
+        kind: &str,
+        field: &str,
+        value: &JsValue,
+        transfer: &Array,
+    ) -> Result<(), JsValue> {
+        let message = Object::new();
+        Reflect::set(&message, &"id".into(), &self.id)?;
+        Reflect::set(&message, &"type".into(), &kind.into())?;
+        Reflect::set(&message, &field.into(), value)?;
+        let post_message: Function = Reflect::get(&self.target, &"postMessage".into())?
+            .dyn_into()
+            .map_err(|_| JsValue::from_str("Reply target cannot receive messages"))?;
+        post_message.call2(&self.target, &message, transfer)?;
+        Ok(())
+    }
+
+    /// A callback for the controller that forwards every progress update.
+    fn progress(&self) -> Closure<dyn FnMut(JsValue)> {
+        let reply = self.clone();
+        Closure::new(move |progress: JsValue| {
+            let _ = reply.post("progress", "progress", &progress, &Array::new());
+        })
+    }
+}
+
+fn field(request: &JsValue, name: &str) -> Result<JsValue, JsValue> {
+    Reflect::get(request, &name.into())
+}
+
+fn string_field(request: &JsValue, name: &str) -> Result<String, JsValue> {
+    field(request, name)?
+        .as_string()
+        .ok_or_else(|| JsValue::from_str(&format!("Missing {}", name)))
+}
+
+fn options(request: &JsValue) -> Result<Option<EncryptionOptions>, JsValue> {
+    let options = field(request, "options")?;
+    if options.is_undefined() || options.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(serde_wasm_bindgen::from_value(options)?))
+}
+
+fn keyfile(request: &JsValue) -> Result<Option<File>, JsValue> {
+    Ok(field(request, "keyfile")?.dyn_into::<File>().ok())
+}
+
+/// The file to encrypt, sent either as a `File` or as an `ArrayBuffer` with its name.
+fn file(request: &JsValue) -> Result<File, JsValue> {
+    if let Ok(file) = field(request, "file")?.dyn_into::<File>() {
+        return Ok(file);
+    }
+    let data = field(request, "data")?
+        .dyn_into::<ArrayBuffer>()
+        .map_err(|_| JsValue::from_str("Missing file"))?;
+    let properties = FilePropertyBag::new();
+    properties.set_type(&string_field(request, "mime_type").unwrap_or_default());
+    File::new_with_buffer_source_sequence_and_options(
+        &Array::of1(&data),
+        &string_field(request, "file_name")?,
+        &properties,
+    )
+}
+
+#[wasm_bindgen]
+impl TsbinWorker {
+    #[wasm_bindgen(constructor)]
+    pub fn new(base_url: String, auth_token: String) -> Result<TsbinWorker, JsValue> {
+        Ok(Self {
+            controller: TsbinController::new(base_url, auth_token, None)?,
+        })
+    }
+
+    /// Serves one request. Answers go to `reply_to`, such as the `MessagePort` handed over
+    /// with a Service Worker message, or else to the worker's own global scope.
+    #[wasm_bindgen]
+    pub async fn handle(&self, request: JsValue, reply_to: JsValue) -> Result<(), JsValue> {
+        let target = match reply_to.is_undefined() || reply_to.is_null() {
+            true => js_sys::global().into(),
+            false => reply_to,
+        };
+        let reply = Reply {
+            target,
+            id: field(&request, "id")?,
+        };
+        match self.serve(&request, &reply).await {
+            Ok((result, transfer)) => reply.post("result", "result", &result, &transfer),
+            Err(error) => {
+                let message = error.as_string().unwrap_or_else(|| format!("{:?}", error));
+                reply.post("error", "message", &message.into(), &Array::new())
+            }
+        }
+    }
+}
+
+impl TsbinWorker {
+    /// Runs the request and returns its result along with the buffers to transfer.
+    async fn serve(&self, request: &JsValue, reply: &Reply) -> Result<(JsValue, Array), JsValue> {
+        let passcode = string_field(request, "passcode").unwrap_or_default();
+        match string_field(request, "op")?.as_str() {
+            "encrypt_text" => {
+                let trash_id = self
+                    .controller
+                    .encrypt_text(
+                        string_field(request, "content")?,
+                        passcode,
+                        options(request)?,
+                    )
+                    .await?;
+                Ok((trash_id.into(), Array::new()))
+            }
+            "decrypt_text" => {
+                let content = self
+                    .controller
+                    .decrypt_text(
+                        string_field(request, "trash_id")?,
+                        passcode,
+                        keyfile(request)?,
+                    )
+                    .await?;
+                Ok((content.value(), Array::new()))
+            }
+            "encrypt_file" => {
+                let progress = reply.progress();
+                let trash_id = self
+                    .controller
+                    .encrypt_file(
+                        file(request)?,
+                        passcode,
+                        options(request)?,
+                        Some(progress.as_ref().unchecked_ref::<Function>().clone()),
+                    )
+                    .await?;
+                Ok((trash_id.into(), Array::new()))
+            }
+            "decrypt_file" => {
+                let progress = reply.progress();
+                let content = self
+                    .controller
+                    .decrypt_file(
+                        string_field(request, "trash_id")?,
+                        passcode,
+                        Some(progress.as_ref().unchecked_ref::<Function>().clone()),
+                        keyfile(request)?,
+                    )
+                    .await?;
+
+                let file = Uint8Array::from(&content.file[..]).buffer();
+                let result = Object::new();
+                Reflect::set(&result, &"id".into(), &content.id.as_str().into())?;
+                Reflect::set(&result, &"file".into(), &file)?;
+                Reflect::set(
+                    &result,
+                    &"file_name".into(),
+                    &content.file_name.as_str().into(),
+                )?;
+                Reflect::set(
+                    &result,
+                    &"mime_type".into(),
+                    &content.mime_type.as_str().into(),
+                )?;
+                Reflect::set(&result, &"file_size".into(), &content.file_size.into())?;
+                Reflect::set(&result, &"signer".into(), &content.signer.clone().into())?;
+                Ok((result.into(), Array::of1(&file)))
+            }
+            other => Err(JsValue::from_str(&format!("Unknown operation: {}", other))),
+        }
+    }
+}